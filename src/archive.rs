@@ -0,0 +1,365 @@
+//! Knowledge of what is already in the Debian archive, read from local apt
+//! `Packages` and `Sources` index files such as those in /var/lib/apt/lists.
+
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use cargo::core::Dependency;
+use clap::Parser;
+use flate2::read::GzDecoder;
+use xz2::read::XzDecoder;
+
+use crate::debian::control::{base_deb_name, deb_feature_name};
+use crate::errors::*;
+use crate::profile::Profile;
+use crate::util;
+
+#[derive(Debug, Clone, Parser)]
+pub struct ArchiveArgs {
+    /// Local apt Packages or Sources index to check dependencies against,
+    /// e.g. from /var/lib/apt/lists. May be compressed with gzip or xz, and
+    /// may be given multiple times.
+    #[arg(long = "archive-index")]
+    pub archive_index: Vec<PathBuf>,
+}
+
+impl ArchiveArgs {
+    /// Load the indexes, whose packages are named as in `profile`.
+    pub fn load(&self, profile: &Profile) -> Result<Option<ArchiveIndex>> {
+        if self.archive_index.is_empty() {
+            Ok(None)
+        } else {
+            ArchiveIndex::load(&self.archive_index, profile).map(Some)
+        }
+    }
+}
+
+/// Versions of Rust crates in the archive.
+#[derive(Debug, Default)]
+pub struct ArchiveIndex {
+    /// Debian versions of each rust-* source package, keyed by the source
+    /// package name without the rust- prefix.
+    versions: BTreeMap<String, BTreeSet<String>>,
+    /// Binary packages and Provides of each rust-* source package, keyed as
+    /// `versions`. Only Packages indexes have these.
+    packages: BTreeMap<String, BTreeSet<String>>,
+    /// The profile whose package name prefixes are used instead of rust- and
+    /// librust-.
+    profile: Profile,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum DepStatus {
+    /// The dependency is satisfied by this version in the archive.
+    Satisfied(String),
+    /// The crate is in the archive in this version, but nothing there
+    /// provides the Debian packages of these features of it.
+    MissingFeatures(String, Vec<String>),
+    /// The crate is in the archive, but only in these versions, none of which
+    /// satisfy the dependency. Newest first.
+    Mismatch(Vec<String>),
+    /// The crate is not in the archive.
+    Missing,
+}
+
+fn read_index(path: &Path) -> Result<String> {
+    let file = File::open(path)?;
+    let mut reader: Box<dyn Read> = match path.extension().and_then(|e| e.to_str()) {
+        Some("gz") => Box::new(GzDecoder::new(file)),
+        Some("xz") => Box::new(XzDecoder::new(file)),
+        Some(ext @ ("bz2" | "lz4" | "zst")) => {
+            debcargo_bail!("unsupported compression .{}; decompress it first", ext)
+        }
+        _ => Box::new(file),
+    };
+    let mut text = String::new();
+    reader.read_to_string(&mut text)?;
+    Ok(text)
+}
+
+impl ArchiveIndex {
+    pub fn load(paths: &[PathBuf], profile: &Profile) -> Result<Self> {
+        let mut index = Self {
+            profile: profile.clone(),
+            ..Self::default()
+        };
+        for path in paths {
+            let text = read_index(path)
+                .with_context(|| format!("failed to read archive index {}", path.display()))?;
+            index.add_index(&text);
+        }
+        Ok(index)
+    }
+
+    /// Add the contents of a Packages or Sources index.
+    pub fn add_index(&mut self, text: &str) {
+        for paragraph in util::parse_deb822(text) {
+            let (package, version) = match (paragraph.get("Package"), paragraph.get("Version")) {
+                (Some(p), Some(v)) => (p.as_str(), v.as_str()),
+                _ => continue,
+            };
+            // binary packages in a Packages index name their source, and its
+            // version if that differs from the binary version
+            let (source, version) = match paragraph.get("Source") {
+                None => (package, version),
+                Some(s) => match s.split_once(' ') {
+                    None => (s.as_str(), version),
+                    Some((s, v)) => (s, v.trim_matches(['(', ')'])),
+                },
+            };
+            let name = match source
+                .strip_prefix(self.profile.source_prefix.as_str())
+                .and_then(|s| s.strip_prefix('-'))
+            {
+                Some(name) => name,
+                None => continue,
+            };
+            self.versions
+                .entry(name.to_string())
+                .or_default()
+                .insert(version.to_string());
+            // Sources paragraphs list their binaries, but not what they provide
+            if paragraph.contains_key("Binary") {
+                continue;
+            }
+            let provides = paragraph.get("Provides").map_or("", String::as_str);
+            let packages = self.packages.entry(name.to_string()).or_default();
+            packages.insert(package.to_string());
+            for p in provides.split(',') {
+                if let Some(p) = p.split_whitespace().next() {
+                    packages.insert(p.to_string());
+                }
+            }
+        }
+    }
+
+    /// Check whether a crate dependency can be satisfied from the archive.
+    /// Its features are only checked against packages from Packages indexes,
+    /// as Sources indexes do not say which features a package provides.
+    pub fn status(&self, dep: &Dependency) -> Result<DepStatus> {
+        let name = base_deb_name(&dep.package_name());
+        // Semver-suffixed packages are named e.g. rust-foo-0.3, but rust-md-5
+        // is the package of md-5, not of md. Only take a suffix as a semver
+        // suffix if it matches the version.
+        let is_version_of_crate = |source: &str, version: &str| {
+            let suffix = source
+                .strip_prefix(name.as_str())
+                .and_then(|r| r.strip_prefix('-'));
+            source == name
+                || matches!((suffix, crate_version(version)), (Some(s), Some(v))
+                    if s == semver_suffix(&v))
+        };
+        let mut versions = self
+            .versions
+            .iter()
+            .flat_map(|(source, versions)| versions.iter().map(move |v| (source, v)))
+            .filter(|(source, v)| is_version_of_crate(source, v))
+            .collect::<Vec<_>>();
+        if versions.is_empty() {
+            return Ok(DepStatus::Missing);
+        }
+        versions.sort_by(|(_, a), (_, b)| compare_versions(b, a));
+        let req = semver::VersionReq::parse(&dep.version_req().to_string())
+            .with_context(|| format!("invalid version requirement for {}", dep.package_name()))?;
+        let mut features = dep
+            .features()
+            .iter()
+            .map(|f| f.as_str())
+            .filter(|f| !f.contains('/'))
+            .collect::<Vec<_>>();
+        if dep.uses_default_features() {
+            features.push("default");
+        }
+        let mut missing_features = None;
+        for (source, v) in &versions {
+            if !matches!(crate_version(v), Some(cv) if req.matches(&cv)) {
+                continue;
+            }
+            let missing = match self.packages.get(source.as_str()) {
+                Some(packages) => features
+                    .iter()
+                    .filter(|f| !packages.contains(&deb_feature_name(&self.profile, source, f)))
+                    .map(|f| f.to_string())
+                    .collect(),
+                None => vec![],
+            };
+            if missing.is_empty() {
+                return Ok(DepStatus::Satisfied(v.to_string()));
+            }
+            missing_features.get_or_insert((v.to_string(), missing));
+        }
+        Ok(match missing_features {
+            Some((v, missing)) => DepStatus::MissingFeatures(v, missing),
+            None => DepStatus::Mismatch(versions.into_iter().map(|(_, v)| v.clone()).collect()),
+        })
+    }
+}
+
+/// The suffix of the package name of a crate with semver_suffix = true.
+fn semver_suffix(v: &semver::Version) -> String {
+    if v.major == 0 {
+        format!("0.{}", v.minor)
+    } else {
+        v.major.to_string()
+    }
+}
+
+/// The crate version that a Debian package version was made from, undoing
+/// `deb_upstream_version` and dropping any repack suffix like +dfsg.
+fn crate_version(deb_version: &str) -> Option<semver::Version> {
+    let v = deb_version.split_once(':').map_or(deb_version, |(_, v)| v);
+    let v = v.rsplit_once('-').map_or(v, |(v, _)| v);
+    let v = v.split('+').next().unwrap();
+    semver::Version::parse(&v.replacen('~', "-", 1)).ok()
+}
+
+// Sort weight of a character in a non-digit part of a version, as in dpkg.
+fn char_order(c: Option<&u8>) -> i32 {
+    match c {
+        None => 0,
+        Some(b'~') => -1,
+        Some(c) if c.is_ascii_alphabetic() => *c as i32,
+        Some(c) => *c as i32 + 256,
+    }
+}
+
+// Compare upstream versions or revisions, as in dpkg's verrevcmp.
+fn compare_version_part(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a.as_bytes(), b.as_bytes());
+    while !a.is_empty() || !b.is_empty() {
+        loop {
+            let ca = a.first().filter(|c| !c.is_ascii_digit());
+            let cb = b.first().filter(|c| !c.is_ascii_digit());
+            if ca.is_none() && cb.is_none() {
+                break;
+            }
+            let ord = char_order(ca).cmp(&char_order(cb));
+            if ord != Ordering::Equal {
+                return ord;
+            }
+            a = &a[usize::from(ca.is_some())..];
+            b = &b[usize::from(cb.is_some())..];
+        }
+        let digits = |s: &[u8]| s.iter().take_while(|c| c.is_ascii_digit()).count();
+        let (da, db) = (digits(a), digits(b));
+        let strip_zeros = |s: &'_ [u8]| {
+            let zeros = s.iter().take_while(|c| **c == b'0').count();
+            s[zeros..].to_vec()
+        };
+        let (na, nb) = (strip_zeros(&a[..da]), strip_zeros(&b[..db]));
+        let ord = na.len().cmp(&nb.len()).then_with(|| na.cmp(&nb));
+        if ord != Ordering::Equal {
+            return ord;
+        }
+        a = &a[da..];
+        b = &b[db..];
+    }
+    Ordering::Equal
+}
+
+/// Compare two Debian package versions, like `dpkg --compare-versions`.
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    let split = |v: &str| {
+        let (epoch, v) = match v.split_once(':') {
+            Some((e, v)) => (e.parse::<u64>().unwrap_or(0), v),
+            None => (0, v),
+        };
+        let (upstream, revision) = v.rsplit_once('-').unwrap_or((v, ""));
+        (epoch, upstream.to_string(), revision.to_string())
+    };
+    let (ea, ua, ra) = split(a);
+    let (eb, ub, rb) = split(b);
+    ea.cmp(&eb)
+        .then_with(|| compare_version_part(&ua, &ub))
+        .then_with(|| compare_version_part(&ra, &rb))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compare_versions, crate_version, ArchiveIndex, DepStatus};
+    use cargo::core::{Dependency, SourceId};
+    use std::cmp::Ordering::*;
+
+    #[test]
+    fn dpkg_version_order() {
+        for (a, b, ord) in [
+            ("1.0-1", "1.0-1", Equal),
+            ("1.0-1", "1.0-2", Less),
+            ("1.0~rc1-1", "1.0-1", Less),
+            ("1.0-1", "1.0+dfsg-1", Less),
+            ("1.10-1", "1.9-1", Greater),
+            ("1:0.1-1", "2.0-1", Greater),
+            ("1.0-1~bpo1", "1.0-1", Less),
+            ("1.0.0", "1.0", Greater),
+            ("1.0a", "1.0", Greater),
+            ("1.01-1", "1.1-1", Equal),
+        ] {
+            assert_eq!(compare_versions(a, b), ord, "{} vs {}", a, b);
+        }
+    }
+
+    #[test]
+    fn versions_from_indexes() {
+        let mut index = ArchiveIndex::default();
+        index.add_index(
+            "Package: librust-foo-dev\n\
+             Source: rust-foo (1.2.3-1)\n\
+             Version: 1.2.3-1+b1\n\
+             Provides: librust-foo-1-dev (= 1.2.3-1+b1)\n\
+             \n\
+             Package: rust-bar-0.3\n\
+             Binary: librust-bar-0.3-dev\n\
+             Version: 0.3.1~beta.2-2\n",
+        );
+        assert_eq!(
+            index.versions.keys().collect::<Vec<_>>(),
+            vec!["bar-0.3", "foo"]
+        );
+        assert_eq!(
+            crate_version("0.3.1~beta.2-2").unwrap().to_string(),
+            "0.3.1-beta.2"
+        );
+        assert_eq!(
+            crate_version("1:1.2.3+dfsg-1").unwrap().to_string(),
+            "1.2.3"
+        );
+    }
+
+    #[test]
+    fn dependency_status() {
+        let mut index = ArchiveIndex::default();
+        index.add_index(
+            "Package: librust-md-5-dev\n\
+             Source: rust-md-5\n\
+             Version: 0.10.6-1\n\
+             Provides: librust-md-5+default-dev (= 0.10.6-1)\n\
+             \n\
+             Package: librust-bar-0.3-dev\n\
+             Source: rust-bar-0.3\n\
+             Version: 0.3.1-1\n\
+             Provides: librust-bar-0.3+default-dev (= 0.3.1-1)\n",
+        );
+        let source_id = SourceId::for_path(std::path::Path::new("/")).unwrap();
+        let dep = |name: &str, req: &str, features: &[&str]| {
+            let mut dep = Dependency::parse(name, Some(req), source_id).unwrap();
+            dep.set_features(features.iter().copied());
+            dep
+        };
+        assert_eq!(
+            index.status(&dep("md", "0.10", &[])).unwrap(),
+            DepStatus::Missing
+        );
+        assert_eq!(
+            index.status(&dep("md-5", "0.10", &[])).unwrap(),
+            DepStatus::Satisfied("0.10.6-1".to_string())
+        );
+        assert_eq!(
+            index.status(&dep("bar", "0.3", &["std"])).unwrap(),
+            DepStatus::MissingFeatures("0.3.1-1".to_string(), vec!["std".to_string()])
+        );
+    }
+}
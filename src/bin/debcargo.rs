@@ -1,14 +1,20 @@
 use ansi_term::Colour::Red;
 use clap::{builder::styling::AnsiColor, builder::Styles, crate_version, Parser, Subcommand};
 
-use debcargo::crates::CrateInfo;
+use debcargo::crates::{CrateInfo, CrateSource};
 use debcargo::debian::DebInfo;
 use debcargo::errors::Result;
 use debcargo::package::*;
+use debcargo::profile::Profile;
 use debcargo::{
+    archive::ArchiveArgs,
     build_order::{build_order, BuildOrderArgs},
     crates::invalidate_crates_io_cache,
+    info::{info, InfoArgs},
+    release::{release, ReleaseArgs},
+    workspace::{package_workspace, WorkspaceArgs},
 };
+use debcargo::{debcargo_bail, debcargo_warn};
 
 const CLI_STYLE: Styles = Styles::styled()
     .header(AnsiColor::Yellow.on_default())
@@ -21,6 +27,11 @@ const CLI_STYLE: Styles = Styles::styled()
 #[command(version)]
 #[command(styles = CLI_STYLE)]
 struct Cli {
+    /// Distribution profile for the generated packaging: debian, ubuntu,
+    /// testing, or a TOML file of custom settings. Takes precedence over the
+    /// profile key of configs.
+    #[arg(long, global = true, env = "DEBCARGO_PROFILE")]
+    profile: Option<String>,
     #[command(subcommand)]
     command: Opt,
 }
@@ -28,7 +39,7 @@ struct Cli {
 #[derive(Debug, Clone, Subcommand)]
 enum Opt {
     /// Update the user's default crates.io index, outside of a workspace.
-    Update,
+    UpdateIndex,
     /// Print the Debian package name for a crate.
     DebSrcName {
         /// Name of the crate to package.
@@ -39,6 +50,12 @@ enum Opt {
         /// option semver_suffix was set to true.
         version: Option<String>,
     },
+    /// Print information about a crate and the Debian packages it maps to,
+    /// as JSON.
+    Info {
+        #[command(flatten)]
+        args: InfoArgs,
+    },
     /// Extract only a crate, without any other transformations.
     Extract {
         #[command(flatten)]
@@ -54,6 +71,60 @@ enum Opt {
         extract: PackageExtractArgs,
         #[command(flatten)]
         finish: PackageExecuteArgs,
+        #[command(flatten)]
+        archive: ArchiveArgs,
+    },
+    /// Package every crate of a local Cargo workspace, dependencies first.
+    Workspace {
+        #[command(flatten)]
+        args: WorkspaceArgs,
+    },
+    /// Check a config file for unknown keys, overrides for packages that the
+    /// crate does not have, and missing paths. Exits with status 1 if there
+    /// are any problems, and 2 if the check itself went wrong.
+    CheckConfig {
+        #[command(flatten)]
+        init: PackageInitArgs,
+    },
+    /// Move existing packaging to a new upstream version of the crate.
+    ///
+    /// This checks that the patches in the overlay still apply and that the
+    /// feature-specific overrides in the config still refer to real features,
+    /// before regenerating the package with a new changelog entry.
+    ///
+    /// Without a crate, this updates the crates.io index like `update-index`,
+    /// as it did in earlier versions; that use is deprecated.
+    Update {
+        #[command(flatten)]
+        init: PackageInitArgs,
+        #[command(flatten)]
+        extract: PackageExtractArgs,
+        #[command(flatten)]
+        finish: PackageExecuteArgs,
+        /// Continue even if the config has overrides for features that the
+        /// new version no longer has.
+        #[arg(long)]
+        allow_stale_overrides: bool,
+    },
+    /// Show what `package` would change in existing packaging, without
+    /// touching it. Like diff(1), exits with status 1 if there are any
+    /// differences, and 2 if something went wrong.
+    Diff {
+        #[command(flatten)]
+        init: PackageInitArgs,
+        #[command(flatten)]
+        finish: PackageExecuteArgs,
+        #[command(flatten)]
+        diff: PackageDiffArgs,
+    },
+    /// Finalize the unreleased changelog entry in a package's overlay for
+    /// upload, setting its distribution and date.
+    ///
+    /// This refuses if the overlay still has FIXMEs, or hint files for files
+    /// that it no longer overrides.
+    Release {
+        #[command(flatten)]
+        args: ReleaseArgs,
     },
     /// Print the transitive dependencies of a package in topological order.
     BuildOrder {
@@ -69,22 +140,66 @@ fn verify_app() {
 }
 
 fn real_main() -> Result<()> {
-    let m = Cli::parse();
+    use clap::{parser::ValueSource, CommandFactory, FromArgMatches};
+    // `update` used to update the crates.io index, which is now done by
+    // `update-index`; keep that working for scripts that still run it. The
+    // positionals are re-indexed since `mut_arg` moves them to the end.
+    let matches = Cli::command()
+        .mut_subcommand("update", |c| {
+            c.mut_arg("crate_name", |a| a.required(false).index(1))
+                .mut_arg("version", |a| a.index(2))
+        })
+        .get_matches();
+    if let Some(("update", sub)) = matches.subcommand() {
+        // --profile is global, so it also shows up here
+        let given = |id: &clap::Id| {
+            id != "profile" && sub.value_source(id.as_str()) == Some(ValueSource::CommandLine)
+        };
+        if sub.ids().any(given) && !sub.contains_id("crate_name") {
+            debcargo_bail!("`debcargo update` needs the name of the crate to update");
+        } else if !sub.contains_id("crate_name") {
+            debcargo_warn!(
+                "`debcargo update` without a crate is deprecated; use `debcargo update-index`"
+            );
+            return invalidate_crates_io_cache();
+        }
+    }
+    let m = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let profile = m
+        .profile
+        .as_deref()
+        .map(|p| Profile::load(p, None))
+        .transpose()?;
+    let profile = profile.as_ref();
     use Opt::*;
     match m.command {
-        Update => invalidate_crates_io_cache(),
+        UpdateIndex => invalidate_crates_io_cache(),
         DebSrcName {
             crate_name,
             version,
         } => {
-            let crate_info = CrateInfo::new_with_update(&crate_name, version.as_deref(), false)?;
-            let deb_info = DebInfo::new(&crate_info, crate_version!(), version.is_some());
+            let crate_info = CrateInfo::new_with_update(
+                &crate_name,
+                version.as_deref(),
+                &CrateSource::CratesIo,
+                false,
+            )?;
+            let deb_info = DebInfo::new(
+                profile.unwrap_or(&Profile::default()),
+                &crate_info,
+                crate_version!(),
+                version.is_some(),
+            );
             println!("{}", deb_info.package_name());
             Ok(())
         }
+        Info { args } => {
+            println!("{}", info(args, profile)?);
+            Ok(())
+        }
         Extract { init, extract } => {
             log::info!("preparing crate info");
-            let mut process = PackageProcess::init(init)?;
+            let mut process = PackageProcess::init(init, profile)?;
             log::info!("extracting crate");
             process.extract(extract)?;
             Ok(())
@@ -93,33 +208,109 @@ fn real_main() -> Result<()> {
             init,
             extract,
             finish,
+            archive,
         } => {
             log::info!("preparing crate info");
-            let mut process = PackageProcess::init(init)?;
+            let mut process = PackageProcess::init(init, profile)?;
             log::info!("extracting crate");
             process.extract(extract)?;
             log::info!("applying overlay and patches");
             process.apply_overrides()?;
+            process.check_config()?;
             log::info!("preparing orig tarball");
             process.prepare_orig_tarball()?;
             log::info!("preparing debian folder");
             process.prepare_debian_folder(finish)?;
+            process.post_package_checks()?;
+            if let Some(archive) = archive.load(process.config.profile())? {
+                process.archive_summary(&archive)?;
+            }
+            Ok(())
+        }
+        Workspace { args } => package_workspace(args, profile),
+        CheckConfig { init } => {
+            if init.config.is_none() {
+                debcargo_bail!("--config is required");
+            }
+            log::info!("preparing crate info");
+            let mut process = PackageProcess::init(init, profile)?;
+            let scratch = tempfile::Builder::new()
+                .prefix("debcargo")
+                .tempdir_in(".")?;
+            log::info!("extracting crate");
+            process.extract(PackageExtractArgs {
+                directory: Some(scratch.path().join(process.deb_info.package_source_dir())),
+            })?;
+            log::info!("applying overlay and patches");
+            process.apply_overrides()?;
+            let ok = process.check_config()?;
+            drop(process);
+            scratch.close()?;
+            if !ok {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        Update {
+            init,
+            extract,
+            finish,
+            allow_stale_overrides,
+        } => {
+            log::info!("preparing crate info");
+            let mut process = PackageProcess::init(init, profile)?;
+            process.update(extract, allow_stale_overrides)?;
+            log::info!("preparing orig tarball");
+            process.prepare_orig_tarball()?;
+            log::info!("preparing debian folder");
+            process.prepare_debian_folder(PackageExecuteArgs {
+                changelog_ready: false,
+                ..finish
+            })?;
             process.post_package_checks()
         }
-        BuildOrder { args } => {
-            let build_order = build_order(args)?;
-            for v in &build_order {
-                println!("{}", v);
+        Diff { init, finish, diff } => {
+            log::info!("preparing crate info");
+            let mut process = PackageProcess::init(init, profile)?;
+            let scratch = tempfile::Builder::new()
+                .prefix("debcargo")
+                .tempdir_in(".")?;
+            log::info!("extracting crate");
+            process.extract(PackageExtractArgs {
+                directory: Some(scratch.path().join(process.deb_info.package_source_dir())),
+            })?;
+            log::info!("applying overlay and patches");
+            process.apply_overrides()?;
+            log::info!("preparing debian folder");
+            process.prepare_debian_folder(PackageExecuteArgs {
+                no_overlay_write_back: true,
+                ..finish
+            })?;
+            let differs = process.diff_debian_folder(&diff.against)?;
+            drop(process);
+            scratch.close()?;
+            if differs {
+                std::process::exit(1);
             }
             Ok(())
         }
+        Release { args } => release(args, profile),
+        BuildOrder { args } => {
+            let format = args.format;
+            print!("{}", build_order(args, profile)?.render(format)?);
+            Ok(())
+        }
     }
 }
 
+/// Exit status on errors. 1 is left for `diff` finding differences, and
+/// `check-config` finding problems.
+const EXIT_ERROR: i32 = 2;
+
 fn main() {
     env_logger::init();
     if let Err(e) = real_main() {
         eprintln!("{}", Red.bold().paint(format!("debcargo failed: {:?}", e)));
-        std::process::exit(1);
+        std::process::exit(EXIT_ERROR);
     }
 }
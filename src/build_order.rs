@@ -1,16 +1,20 @@
 use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
-use std::fmt;
+use std::fmt::{self, Write};
 use std::path::{Path, PathBuf};
 
-use anyhow::Context;
 use cargo::core::{Dependency, PackageId};
 use clap::{Parser, ValueEnum};
+use serde::Serialize;
 
-use crate::config::Config;
-use crate::crates::{crate_name_ver_to_dep, show_dep, transitive_deps, CrateDepInfo, CrateInfo};
-use crate::debian::control::base_deb_name;
+use crate::archive::{ArchiveArgs, DepStatus};
+use crate::config::{find_config, Config, DefaultsArgs};
+use crate::crates::{
+    crate_name_ver_to_dep, show_dep, transitive_deps, CrateDepInfo, CrateInfo, CrateSource,
+};
+use crate::debian::{deb_dep_add_nocheck, deb_deps};
 use crate::errors::Result;
 use crate::package::{PackageExtractArgs, PackageProcess};
+use crate::profile::Profile;
 use crate::util;
 
 #[derive(Debug, Clone, Copy, ValueEnum)]
@@ -20,6 +24,17 @@ pub enum ResolveType {
     BinaryAllForDebianTesting,
 }
 
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum OutputFormat {
+    /// The crates in build order, one per line.
+    Text,
+    /// The dependency graph between crate features, for graphviz. Crates
+    /// with a config are drawn as boxes, and soft dependencies are dashed.
+    Dot,
+    /// The build order and the dependency graph between crate features.
+    Json,
+}
+
 #[derive(Debug, Clone, Parser)]
 pub struct BuildOrderArgs {
     /// Name of the crate to package.
@@ -33,12 +48,26 @@ pub struct BuildOrderArgs {
     /// subpath of the looked-up subdirectory.
     #[arg(long)]
     config_dir: Option<PathBuf>,
+    #[command(flatten)]
+    defaults: DefaultsArgs,
     /// Resolution type
     #[arg(value_enum, long, default_value = "SourceForDebianUnstable")]
     resolve_type: ResolveType,
     /// Emulate resolution as if every package were built with --collapse-features.
     #[arg(long)]
     emulate_collapse_features: bool,
+    /// Output format
+    #[arg(value_enum, long, default_value = "text")]
+    pub format: OutputFormat,
+    /// Where to get crates from: crates-io, the name of a registry in cargo's
+    /// config, local-registry:PATH or directory:PATH. Dependencies on
+    /// crates.io crates are also taken from here.
+    #[arg(long, visible_alias = "source")]
+    registry: Option<CrateSource>,
+    // dependencies already satisfied by the archive are left out, along with
+    // their own dependencies
+    #[command(flatten)]
+    archive: ArchiveArgs,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
@@ -50,15 +79,117 @@ impl fmt::Display for PackageIdFeat {
     }
 }
 
+type FeatGraph = BTreeMap<PackageIdFeat, BTreeSet<PackageIdFeat>>;
+
+/// The packages in the order they need to be built, along with the graph
+/// that this order was calculated from.
+pub struct BuildOrder {
+    pub order: Vec<PackageId>,
+    /// Whether a config was found for each package in --config-dir.
+    has_config: BTreeMap<PackageId, bool>,
+    hard: FeatGraph,
+    soft: FeatGraph,
+}
+
+#[derive(Serialize)]
+struct JsonNode {
+    id: String,
+    #[serde(rename = "crate")]
+    crate_name: String,
+    version: String,
+    feature: &'static str,
+    has_config: bool,
+}
+
+#[derive(Serialize)]
+struct JsonEdge {
+    from: String,
+    to: String,
+    kind: &'static str,
+}
+
+#[derive(Serialize)]
+struct JsonBuildOrder {
+    order: Vec<String>,
+    nodes: Vec<JsonNode>,
+    edges: Vec<JsonEdge>,
+}
+
+impl BuildOrder {
+    fn edges(&self) -> impl Iterator<Item = (&PackageIdFeat, &PackageIdFeat, &'static str)> {
+        self.hard
+            .iter()
+            .flat_map(|(k, vv)| vv.iter().map(move |v| (k, v, "hard")))
+            .chain(
+                self.soft
+                    .iter()
+                    .flat_map(|(k, vv)| vv.iter().map(move |v| (k, v, "soft"))),
+            )
+    }
+
+    pub fn render(&self, format: OutputFormat) -> Result<String> {
+        let mut out = String::new();
+        match format {
+            OutputFormat::Text => {
+                for v in &self.order {
+                    writeln!(out, "{}", v)?;
+                }
+            }
+            OutputFormat::Dot => {
+                writeln!(out, "digraph build_order {{")?;
+                for idf in self.hard.keys() {
+                    let shape = if self.has_config[&idf.0] {
+                        "box"
+                    } else {
+                        "ellipse"
+                    };
+                    writeln!(out, "  \"{}\" [shape={}];", idf, shape)?;
+                }
+                for (from, to, kind) in self.edges() {
+                    let style = if kind == "soft" { "dashed" } else { "solid" };
+                    writeln!(out, "  \"{}\" -> \"{}\" [style={}];", from, to, style)?;
+                }
+                writeln!(out, "}}")?;
+            }
+            OutputFormat::Json => {
+                let json = JsonBuildOrder {
+                    order: self.order.iter().map(|v| v.to_string()).collect(),
+                    nodes: self
+                        .hard
+                        .keys()
+                        .map(|idf| JsonNode {
+                            id: idf.to_string(),
+                            crate_name: idf.0.name().to_string(),
+                            version: idf.0.version().to_string(),
+                            feature: idf.1,
+                            has_config: self.has_config[&idf.0],
+                        })
+                        .collect(),
+                    edges: self
+                        .edges()
+                        .map(|(from, to, kind)| JsonEdge {
+                            from: from.to_string(),
+                            to: to.to_string(),
+                            kind,
+                        })
+                        .collect(),
+                };
+                writeln!(out, "{}", serde_json::to_string_pretty(&json)?)?;
+            }
+        }
+        Ok(out)
+    }
+}
+
 // First result: if somebody build-depends on us, what do they first need to build?
 // Second result: what other packages need to go into Debian Testing before us?
 fn get_build_deps(
-    crate_details: &(CrateInfo, CrateDepInfo, Config),
+    crate_details: &(CrateInfo, CrateDepInfo, Config, Option<PathBuf>),
     package: &PackageIdFeat,
     resolve_type: ResolveType,
     emulate_collapse_features: bool,
 ) -> Result<(Vec<Dependency>, Vec<Dependency>)> {
-    let (_, crate_dep_info, config) = crate_details;
+    let (_, crate_dep_info, config, _) = crate_details;
     let all_deps = crate_dep_info
         .iter()
         .flat_map(|(_, v)| v.1.iter())
@@ -103,33 +234,12 @@ fn dep_features(dep: &Dependency) -> Vec<&'static str> {
     feats
 }
 
-fn find_config(config_dir: &Path, id: PackageId) -> Result<(Option<PathBuf>, Config)> {
-    let name = base_deb_name(&id.name());
-    let version = id.version();
-    let candidates = [
-        format!(
-            "{}-{}.{}.{}",
-            name, version.major, version.minor, version.patch
-        ),
-        format!("{}-{}.{}", name, version.major, version.minor),
-        format!("{}-{}", name, version.major),
-        name,
-    ];
-    for c in candidates {
-        let path = config_dir.join(c).join("debian").join("debcargo.toml");
-        if path.is_file() {
-            let config = Config::parse(&path).context("failed to parse debcargo.toml")?;
-            log::debug!("using config for {}: {:?}", id, path);
-            return Ok((Some(path), config));
-        }
-    }
-    Ok((None, Config::default()))
-}
-
 fn resolve_info(
-    infos: &mut BTreeMap<PackageId, (CrateInfo, CrateDepInfo, Config)>,
+    infos: &mut BTreeMap<PackageId, (CrateInfo, CrateDepInfo, Config, Option<PathBuf>)>,
     cache: &mut HashMap<Dependency, PackageId>,
     config_dir: Option<&Path>,
+    defaults: Option<&Path>,
+    profile: Option<&Profile>,
     dependency: &Dependency,
     update: bool,
 ) -> Result<PackageId> {
@@ -146,20 +256,22 @@ fn resolve_info(
     if let std::collections::btree_map::Entry::Vacant(e) = infos.entry(id) {
         let id = *e.key();
         let default_config = Config::default();
-        let (config_path, config) = match config_dir {
+        let (config_path, mut config) = match config_dir {
             None => (None, default_config),
             Some(config_dir) => {
-                let (config_path, config) = find_config(config_dir, id)?;
+                let (config_path, config) =
+                    find_config(config_dir, defaults, &id.name(), id.version())?;
                 match config_path {
                     None => (None, default_config),
                     Some(_) => (config_path, config),
                 }
             }
         };
+        config.override_profile(profile);
         let (info, config) = match config_path {
             None => (info, config),
             Some(_) => {
-                let mut process = PackageProcess::new(info, config_path, config)?;
+                let mut process = PackageProcess::new(info, config_path.clone(), config)?;
                 let tempdir = tempfile::Builder::new()
                     .prefix("debcargo")
                     .tempdir_in(".")?;
@@ -171,21 +283,165 @@ fn resolve_info(
             }
         };
         let dep_info = info.all_dependencies_and_features();
-        e.insert((info, dep_info, config));
+        e.insert((info, dep_info, config, config_path));
     };
     Ok(id)
 }
 
-pub fn build_order(args: BuildOrderArgs) -> Result<Vec<PackageId>> {
+/// Print the cycles in each strongly connected component of the crate-level
+/// build graph `succ`, and suggest `build_depends_excludes` entries that
+/// would break all of them.
+///
+/// An edge from crate A to crate B can only be broken this way if it exists
+/// because B is a dependency of A's default features, which are pulled in
+/// when building A, and not because some feature of A depends on B.
+fn explain_cycles(
+    succ: &BTreeMap<PackageId, BTreeSet<PackageId>>,
+    succ_with_features: &FeatGraph,
+    infos: &BTreeMap<PackageId, (CrateInfo, CrateDepInfo, Config, Option<PathBuf>)>,
+    cache: &HashMap<Dependency, PackageId>,
+) -> Result<()> {
+    for component in util::strongly_connected_components(succ) {
+        // for each edge within the component, the feature-level edges that
+        // it comes from, and the excludes that would break it if possible
+        let mut edges = BTreeMap::new();
+        for p in &component {
+            for q in succ[p].iter().filter(|q| component.contains(q)) {
+                let (crate_info, crate_dep_info, config, _) = &infos[p];
+                let resolves_to_q = |d: &Dependency| cache.get(d) == Some(q);
+
+                let mut lines = Vec::new();
+                let mut breakable = true;
+                for (from, tos) in succ_with_features.range(PackageIdFeat(*p, "")..) {
+                    if from.0 != *p {
+                        break;
+                    }
+                    for to in tos.iter().filter(|to| to.0 == *q) {
+                        let needed = transitive_deps(crate_dep_info, from.1)?
+                            .1
+                            .iter()
+                            .any(resolves_to_q);
+                        lines.push(format!(
+                            "{} -> {}{}",
+                            from,
+                            to,
+                            if needed { "" } else { " (default features)" }
+                        ));
+                        breakable &= !needed;
+                    }
+                }
+                let has_bins = !crate_info.get_binary_targets().is_empty()
+                    && (!crate_info.is_lib() || config.build_bin_package());
+                let entries = if breakable && !has_bins {
+                    let default_deps = transitive_deps(crate_dep_info, "default")?.1;
+                    let mut entries = BTreeSet::new();
+                    for dep in default_deps.iter().filter(|&d| resolves_to_q(d)) {
+                        entries.extend(
+                            deb_deps(config, std::slice::from_ref(dep))?
+                                .iter()
+                                .map(|d| deb_dep_add_nocheck(d)),
+                        );
+                    }
+                    Some(entries)
+                } else {
+                    None
+                };
+                edges.insert((*p, *q), (lines, entries));
+            }
+        }
+
+        let mut excludes = BTreeMap::new();
+        let mut unbreakable = false;
+        let cycles = util::cycles_to_break(succ, &component, |p, q| edges[&(*p, *q)].1.is_some());
+        for (cycle, chosen) in cycles {
+            debcargo_warn!("Dependency cycle between {} crates:", cycle.len());
+            for (i, p) in cycle.iter().enumerate() {
+                let q = &cycle[(i + 1) % cycle.len()];
+                for line in &edges[&(*p, *q)].0 {
+                    debcargo_warn!("\t{}", line);
+                }
+            }
+            match chosen {
+                Some((p, q)) => {
+                    let config_path = infos[&p].3.as_ref();
+                    let entries: &mut BTreeSet<String> =
+                        excludes.entry((p, config_path)).or_default();
+                    entries.extend(edges[&(p, q)].1.iter().flatten().cloned());
+                }
+                None => {
+                    debcargo_warn!("\tThis cycle cannot be broken with build_depends_excludes.");
+                    unbreakable = true;
+                }
+            }
+        }
+        if excludes.is_empty() {
+            continue;
+        }
+        if unbreakable {
+            debcargo_warn!("To break the other cycles, add all of the following:");
+        } else {
+            debcargo_warn!("To break these cycles, add all of the following:");
+        }
+        for ((p, config_path), entries) in excludes {
+            match config_path {
+                Some(c) => debcargo_warn!("\t •  In {}:", c.display()),
+                None => debcargo_warn!("\t •  In a new config for {}:", p),
+            }
+            debcargo_warn!("\t    [source]");
+            debcargo_warn!(
+                "\t    build_depends_excludes = [{}]",
+                entries
+                    .iter()
+                    .map(|e| format!("\"{}\"", e))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+        debcargo_warn!("\tYou may also need to override dh_auto_test in d/rules, to avoid");
+        debcargo_warn!("\tselecting the excluded dependencies when running the test build.");
+    }
+    Ok(())
+}
+
+/// `profile` overrides the one that the configs select, if given.
+pub fn build_order(args: BuildOrderArgs, profile: Option<&Profile>) -> Result<BuildOrder> {
     let crate_name = &args.crate_name;
     let version = args.version.as_deref();
     let config_dir = args.config_dir.as_deref();
+    let defaults = args.defaults.for_config_dir(config_dir);
+    let defaults = defaults.as_deref();
 
     let mut infos = BTreeMap::new();
     let mut cache = HashMap::new();
-    let seed_dep = crate_name_ver_to_dep(crate_name, version)?;
-    let seed_id = resolve_info(&mut infos, &mut cache, config_dir, &seed_dep, true)?;
+    let source = args.registry.clone().unwrap_or_default();
+    let seed_dep = crate_name_ver_to_dep(crate_name, version, &source)?;
+    // crates from other sources still depend on crates.io, so redirect those
+    // dependencies to the same source
+    let from_source = |dep: Dependency| {
+        if dep.source_id().is_default_registry() && !seed_dep.source_id().is_default_registry() {
+            let crates_io = dep.source_id();
+            dep.map_source(crates_io, seed_dep.source_id())
+        } else {
+            dep
+        }
+    };
+    let seed_id = resolve_info(
+        &mut infos, &mut cache, config_dir, defaults, profile, &seed_dep, true,
+    )?;
 
+    // the archive is expected to use the package names of the seed's profile
+    let archive = args.archive.load(infos[&seed_id].2.profile())?;
+    let in_archive = |dep: &Dependency| -> Result<bool> {
+        Ok(match archive.as_ref().map(|a| a.status(dep)).transpose()? {
+            Some(DepStatus::Satisfied(v)) => {
+                log::debug!("{} satisfied by archive version {}", show_dep(dep), v);
+                true
+            }
+            _ => false,
+        })
+    };
+
+    let mut soft_succ_with_features: FeatGraph = BTreeMap::new();
     let mut next = |idf: &PackageIdFeat| -> Result<(Vec<PackageIdFeat>, Vec<PackageIdFeat>)> {
         log::trace!("{} getting build deps..", idf);
         let (hard, soft) = get_build_deps(
@@ -205,14 +461,26 @@ pub fn build_order(args: BuildOrderArgs) -> Result<Vec<PackageId>> {
         // version ranges) might resolve into the same crate-version
         let mut hard_p = Vec::new();
         for dep in hard {
-            let id = resolve_info(&mut infos, &mut cache, config_dir, &dep, false)?;
+            if in_archive(&dep)? {
+                continue;
+            }
+            let dep = from_source(dep);
+            let id = resolve_info(
+                &mut infos, &mut cache, config_dir, defaults, profile, &dep, false,
+            )?;
             for f in dep_features(&dep) {
                 hard_p.push(PackageIdFeat(id, f));
             }
         }
         let mut soft_p = Vec::new();
         for dep in soft {
-            let id = resolve_info(&mut infos, &mut cache, config_dir, &dep, false)?;
+            if in_archive(&dep)? {
+                continue;
+            }
+            let dep = from_source(dep);
+            let id = resolve_info(
+                &mut infos, &mut cache, config_dir, defaults, profile, &dep, false,
+            )?;
             for f in dep_features(&dep) {
                 soft_p.push(PackageIdFeat(id, f));
             }
@@ -220,6 +488,7 @@ pub fn build_order(args: BuildOrderArgs) -> Result<Vec<PackageId>> {
         log::trace!("{} hard-dep resolve: {}", idf, util::show_vec(&hard_p));
         if !soft_p.is_empty() {
             log::trace!("{} soft-dep resolve: {}", idf, util::show_vec(&soft_p));
+            soft_succ_with_features.insert(idf.clone(), BTreeSet::from_iter(soft_p.clone()));
         }
         Ok((hard_p, soft_p))
     };
@@ -250,7 +519,7 @@ pub fn build_order(args: BuildOrderArgs) -> Result<Vec<PackageId>> {
     let build_order = match util::topo_sort(roots, pred.clone(), succ.clone()) {
         Ok(r) => r,
         Err(remain) => {
-            log::error!(
+            log::debug!(
                 "topo_sort got cyclic graph: {:#?}",
                 remain
                     .into_iter()
@@ -262,12 +531,18 @@ pub fn build_order(args: BuildOrderArgs) -> Result<Vec<PackageId>> {
                     ))
                     .collect::<BTreeMap<_, _>>()
             );
+            explain_cycles(&succ, &succ_with_features, &infos, &cache)?;
             debcargo_bail!(
                 "topo_sort got cyclic graph; you'll need to patch the crate(s) to break the cycle."
             )
         }
     };
 
+    let has_config = infos
+        .iter()
+        .map(|(id, info)| (*id, info.3.is_some()))
+        .collect();
+
     // sanity check
     for p in build_order.iter() {
         if infos.remove(p).is_none() {
@@ -283,5 +558,10 @@ pub fn build_order(args: BuildOrderArgs) -> Result<Vec<PackageId>> {
         );
     }
 
-    Ok(build_order)
+    Ok(BuildOrder {
+        order: build_order,
+        has_config,
+        hard: succ_with_features,
+        soft: soft_succ_with_features,
+    })
 }
@@ -1,16 +1,22 @@
+use anyhow::Context;
+use clap::Parser;
+use semver::Version;
 use serde::Deserialize;
 use toml;
 
+use crate::crates::CrateSource;
+use crate::debian::arch::ArchTable;
+use crate::debian::control::base_deb_name;
+use crate::debian::syslibs::SysLibMap;
 use crate::errors::*;
+use crate::profile::Profile;
+use crate::util;
 
-use std::collections::HashMap;
-use std::fs::File;
-use std::io::Read;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::fs;
 use std::path::{Path, PathBuf};
 
-pub const RUST_MAINT: &str =
-    "Debian Rust Maintainers <pkg-rust-maintainers@alioth-lists.debian.net>";
-
 #[derive(Deserialize, Debug, Clone)]
 #[serde(default)]
 pub struct Config {
@@ -22,15 +28,27 @@ pub struct Config {
     pub whitelist: Option<Vec<String>>,
     pub allow_prerelease_deps: bool,
     pub crate_src_path: Option<PathBuf>,
+    pub registry: Option<String>,
+    pub syslib_map: Option<PathBuf>,
+    pub upstream_clone: Option<PathBuf>,
     pub summary: Option<String>,
     pub description: Option<String>,
-    pub maintainer: String,
+    pub maintainer: Option<String>,
     pub uploaders: Option<Vec<String>>,
     pub collapse_features: bool,
     pub requires_root: Option<String>,
+    pub profile: Option<String>,
 
     pub source: Option<SourceOverride>,
     pub packages: Option<HashMap<String, PackageOverride>>,
+    pub architectures: Option<BTreeMap<String, ArchOverride>>,
+
+    /// The defaults file that this config was merged over, if any.
+    #[serde(skip)]
+    pub defaults: Option<PathBuf>,
+    /// The profile named by `profile`, or the one that overrides it.
+    #[serde(skip)]
+    selected_profile: Profile,
 }
 
 #[derive(Deserialize, Debug, Clone, Default)]
@@ -42,6 +60,7 @@ pub struct SourceOverride {
     vcs_browser: Option<String>,
     build_depends: Option<Vec<String>>,
     build_depends_excludes: Option<Vec<String>>,
+    debhelper_compat: Option<u32>,
 }
 
 #[derive(Deserialize, Debug, Clone, Default)]
@@ -58,6 +77,91 @@ pub struct PackageOverride {
     test_depends: Option<Vec<String>>,
 }
 
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct ArchOverride {
+    triple: Option<String>,
+    cfg: Option<Vec<String>>,
+    ignore: Option<bool>,
+}
+
+/// The keys that serde accepts for a config table, i.e. the fields of the
+/// struct it is deserialized into, to catch typos that serde silently ignores.
+fn table_keys<'de, T: Deserialize<'de>>() -> &'static [&'static str] {
+    use serde::de::{self, Visitor};
+
+    struct FieldNames(&'static [&'static str]);
+
+    impl<'de> de::Deserializer<'de> for &mut FieldNames {
+        type Error = de::value::Error;
+
+        fn deserialize_any<V: Visitor<'de>>(
+            self,
+            _visitor: V,
+        ) -> std::result::Result<V::Value, Self::Error> {
+            Err(de::Error::custom("not a struct"))
+        }
+
+        fn deserialize_struct<V: Visitor<'de>>(
+            self,
+            _name: &'static str,
+            fields: &'static [&'static str],
+            _visitor: V,
+        ) -> std::result::Result<V::Value, Self::Error> {
+            self.0 = fields;
+            Err(de::Error::custom("only the field names are wanted"))
+        }
+
+        serde::forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+            bytes byte_buf option unit unit_struct newtype_struct seq tuple
+            tuple_struct map enum identifier ignored_any
+        }
+    }
+
+    let mut names = FieldNames(&[]);
+    let _ = T::deserialize(&mut names);
+    names.0
+}
+
+fn check_keys(value: &toml::Value, table: &str, known: &[&str], problems: &mut Vec<String>) {
+    let table_value = match value.as_table() {
+        Some(t) => t,
+        None => return,
+    };
+    for key in table_value.keys() {
+        if known.contains(&key.as_str()) {
+            continue;
+        }
+        let mut msg = format!("unknown key {}{}", table, key);
+        if let Some(m) = util::closest_match(key, known.iter().copied()) {
+            msg.push_str(&format!("; did you mean {}{}?", table, m));
+        }
+        problems.push(msg);
+    }
+}
+
+/// Check the keys in every table of a config file.
+fn check_file_keys(src: &Path, problems: &mut Vec<String>) -> Result<()> {
+    let value: toml::Value = toml::from_str(&fs::read_to_string(src)?)?;
+    check_keys(&value, "", table_keys::<Config>(), problems);
+    if let Some(source) = value.get("source") {
+        check_keys(source, "source.", table_keys::<SourceOverride>(), problems);
+    }
+    if let Some(packages) = value.get("packages").and_then(toml::Value::as_table) {
+        for (key, package) in packages {
+            let table = format!("packages.\"{}\".", key);
+            check_keys(package, &table, table_keys::<PackageOverride>(), problems);
+        }
+    }
+    if let Some(arches) = value.get("architectures").and_then(toml::Value::as_table) {
+        for (key, arch) in arches {
+            let table = format!("architectures.{}.", key);
+            check_keys(arch, &table, table_keys::<ArchOverride>(), problems);
+        }
+    }
+    Ok(())
+}
+
 impl Default for Config {
     fn default() -> Self {
         Config {
@@ -69,25 +173,279 @@ impl Default for Config {
             whitelist: None,
             allow_prerelease_deps: false,
             crate_src_path: None,
+            registry: None,
+            syslib_map: None,
+            upstream_clone: None,
             summary: None,
             description: None,
-            maintainer: RUST_MAINT.to_string(),
+            maintainer: None,
             uploaders: None,
             collapse_features: false,
             source: None,
             packages: None,
+            architectures: None,
             requires_root: None,
+            profile: None,
+            defaults: None,
+            selected_profile: Profile::default(),
         }
     }
 }
 
+/// Name of the defaults file that is looked up in a directory of configs.
+pub const DEFAULTS_FILE: &str = "debcargo-defaults.toml";
+
+#[derive(Debug, Clone, Default, Parser)]
+pub struct DefaultsArgs {
+    /// TOML file with team-wide defaults, which each crate's config is merged
+    /// over. If not given, debcargo-defaults.toml in the directory of configs
+    /// is used if it exists.
+    #[arg(long, env = "DEBCARGO_DEFAULTS")]
+    pub defaults: Option<PathBuf>,
+}
+
+impl DefaultsArgs {
+    /// The defaults file for the configs in `config_dir`.
+    pub fn for_config_dir(&self, config_dir: Option<&Path>) -> Option<PathBuf> {
+        match self.defaults {
+            Some(ref p) => Some(p.clone()),
+            None => Some(config_dir?.join(DEFAULTS_FILE)).filter(|p| p.is_file()),
+        }
+    }
+
+    /// The defaults file for a single config. The directory of configs is
+    /// only known if the config is laid out as in debcargo-conf, i.e. as
+    /// <config-dir>/<crate>/debian/debcargo.toml.
+    pub fn for_config(&self, config_path: &Path) -> Option<PathBuf> {
+        let debian_dir = config_path.parent().filter(|p| p.ends_with("debian"));
+        self.for_config_dir(debian_dir.and_then(Path::parent).and_then(Path::parent))
+    }
+}
+
+/// The file that each value of a merged config came from, by dotted key.
+pub type Origins = BTreeMap<String, PathBuf>;
+
+/// A key as written in a config, quoted if needed.
+fn show_key(key: &str) -> String {
+    if !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        key.to_string()
+    } else {
+        format!("{:?}", key)
+    }
+}
+
+/// Record `origin` for every value in `table` that replaces the one below it
+/// when merging, see `merge_over`.
+fn record_origins(table: &toml::value::Table, origin: &Path, origins: &mut Origins) {
+    for (key, value) in table {
+        match value.as_table() {
+            Some(t) => {
+                for k in t.keys() {
+                    let dotted = format!("{}.{}", show_key(key), show_key(k));
+                    origins.insert(dotted, origin.to_path_buf());
+                }
+            }
+            None => {
+                origins.insert(show_key(key), origin.to_path_buf());
+            }
+        }
+    }
+}
+
+/// Merge a config over defaults. Top-level tables are merged per key, so
+/// that fields of [source] and whole entries of [packages] and
+/// [architectures] replace those of the defaults; other values replace
+/// those of the defaults entirely. This is deliberately only one level deep:
+/// an entry of [packages] is not merged with the same entry in the defaults.
+fn merge_over(mut base: toml::value::Table, config: toml::value::Table) -> toml::value::Table {
+    for (key, value) in config {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(b)), toml::Value::Table(t)) => b.extend(t),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+    base
+}
+
+/// Read the config at `src`, merged over the defaults file if any, as TOML,
+/// together with the file that each value came from. Paths in the defaults
+/// are relative to the config they are merged under, not to the defaults
+/// file, except for syslib_map, see `Config::parse_with_defaults`.
+pub fn load_layered(src: &Path, defaults: Option<&Path>) -> Result<(toml::value::Table, Origins)> {
+    let read = |p: &Path| -> Result<toml::value::Table> {
+        toml::from_str(&fs::read_to_string(p)?)
+            .with_context(|| format!("failed to parse {}", p.display()))
+    };
+    let mut origins = Origins::new();
+    let base = match defaults {
+        Some(d) => {
+            let table = read(d)?;
+            record_origins(&table, d, &mut origins);
+            table
+        }
+        None => toml::value::Table::new(),
+    };
+    let table = read(src)?;
+    record_origins(&table, src, &mut origins);
+    Ok((merge_over(base, table), origins))
+}
+
+fn show_layered_value(
+    out: &mut String,
+    key: &str,
+    value: &toml::Value,
+    origin: &Path,
+) -> Result<()> {
+    match value.as_table() {
+        Some(t) => {
+            for (k, v) in t {
+                let key = format!("{}.{}", key, show_key(k));
+                show_layered_value(out, &key, v, origin)?;
+            }
+        }
+        None => writeln!(out, "{} = {}  # {}", key, value, origin.display())?,
+    }
+    Ok(())
+}
+
+/// The config at `src` merged over the defaults file if any, one value per
+/// line as dotted keys, each followed by the file that it came from.
+pub fn show_layered(src: &Path, defaults: Option<&Path>) -> Result<String> {
+    let (table, origins) = load_layered(src, defaults)?;
+    let origin = |key: &str| origins.get(key).map(PathBuf::as_path).unwrap_or(src);
+    let mut out = String::new();
+    for (key, value) in &table {
+        let key = show_key(key);
+        match value.as_table() {
+            Some(t) => {
+                for (k, v) in t {
+                    let key = format!("{}.{}", key, show_key(k));
+                    show_layered_value(&mut out, &key, v, origin(&key))?;
+                }
+            }
+            None => show_layered_value(&mut out, &key, value, origin(&key))?,
+        }
+    }
+    Ok(out)
+}
+
 impl Config {
     pub fn parse(src: &Path) -> Result<Config> {
-        let mut config_file = File::open(src)?;
-        let mut content = String::new();
-        config_file.read_to_string(&mut content)?;
+        Config::parse_with_defaults(src, None)
+    }
 
-        Ok(toml::from_str(&content)?)
+    /// Parse the config at `src`, merged over the defaults file if given.
+    ///
+    /// A syslib_map or profile file from the defaults is meant to be shared
+    /// by all configs, so it is resolved relative to the defaults file instead.
+    pub fn parse_with_defaults(src: &Path, defaults: Option<&Path>) -> Result<Config> {
+        let (table, origins) = load_layered(src, defaults)?;
+        let mut config: Config = toml::Value::Table(table).try_into()?;
+        if let (Some(defaults), Some(syslib_map)) = (defaults, config.syslib_map.as_mut()) {
+            if origins.get("syslib_map").map(PathBuf::as_path) == Some(defaults) {
+                let dir = std::env::current_dir()?.join(defaults);
+                *syslib_map = dir.parent().unwrap().join(&syslib_map);
+            }
+        }
+        if let Some(ref name) = config.profile {
+            let origin = origins.get("profile").map(PathBuf::as_path).unwrap_or(src);
+            config.selected_profile = Profile::load(name, origin.parent())?;
+        }
+        config.defaults = defaults.map(Path::to_path_buf);
+        Ok(config)
+    }
+
+    /// The distribution profile to generate the packaging for.
+    pub fn profile(&self) -> &Profile {
+        &self.selected_profile
+    }
+
+    /// Use `profile` instead of the one that the config selects, if given,
+    /// e.g. because it was given on the command line.
+    pub fn override_profile(&mut self, profile: Option<&Profile>) {
+        if let Some(profile) = profile {
+            self.selected_profile = profile.clone();
+        }
+    }
+
+    /// Check the config file at `src`, which this config was parsed from,
+    /// for mistakes that parsing does not catch: unknown keys, package
+    /// overrides for features that the crate does not have, and paths that
+    /// do not exist. `features` are the features of the crate being packaged.
+    ///
+    /// Returns a message for each problem found.
+    pub fn validate(&self, src: &Path, features: &[&str]) -> Result<Vec<String>> {
+        let mut problems = Vec::new();
+
+        check_file_keys(src, &mut problems)?;
+        if let Some(ref defaults) = self.defaults {
+            let mut defaults_problems = Vec::new();
+            check_file_keys(defaults, &mut defaults_problems)?;
+            problems.extend(
+                defaults_problems
+                    .into_iter()
+                    .map(|p| format!("{}: {}", defaults.display(), p)),
+            );
+        }
+        if let Err(e) = self.arch_table() {
+            problems.push(format!("architectures: {}", e));
+        }
+
+        let package_keys = ["bin", "lib", "lib+@"]
+            .iter()
+            .map(|k| k.to_string())
+            .chain(
+                features
+                    .iter()
+                    .filter(|f| !f.is_empty())
+                    .map(|f| format!("lib+{}", f)),
+            )
+            .collect::<Vec<_>>();
+        let mut unknown = self
+            .packages
+            .iter()
+            .flat_map(|p| p.keys())
+            .filter(|k| !package_keys.contains(k))
+            .collect::<Vec<_>>();
+        unknown.sort();
+        for key in unknown {
+            let mut msg = format!("packages.\"{}\" does not match any binary package", key);
+            if let Some(m) = util::closest_match(key, package_keys.iter().map(String::as_str)) {
+                msg.push_str(&format!("; did you mean packages.\"{}\"?", m));
+            }
+            problems.push(msg);
+        }
+
+        let source_path = match self.crate_source(Some(src)) {
+            Ok(CrateSource::LocalRegistry(p) | CrateSource::Directory(p)) => Some(p),
+            Ok(_) => None,
+            Err(e) => {
+                problems.push(format!("registry: {}", e));
+                None
+            }
+        };
+        for (key, path) in [
+            ("overlay", self.overlay_dir(Some(src))),
+            ("crate_src_path", self.crate_src_path(Some(src))),
+            ("registry", source_path),
+            ("syslib_map", self.syslib_map_path(Some(src))),
+            ("upstream_clone", self.upstream_clone(Some(src))),
+        ] {
+            match path {
+                Some(p) if !p.exists() => {
+                    problems.push(format!("{} {} does not exist", key, p.display()))
+                }
+                _ => (),
+            }
+        }
+
+        Ok(problems)
     }
 
     pub fn build_bin_package(&self) -> bool {
@@ -105,6 +463,33 @@ impl Config {
         Some(config_path?.parent()?.join(self.crate_src_path.as_ref()?))
     }
 
+    /// Where to get the crate from. Paths are relative to the directory that
+    /// contains the config file.
+    pub fn crate_source(&self, config_path: Option<&Path>) -> Result<CrateSource> {
+        let source = match self.registry.as_deref() {
+            None => return Ok(CrateSource::CratesIo),
+            Some(s) => s.parse::<CrateSource>()?,
+        };
+        Ok(match config_path.and_then(Path::parent) {
+            Some(dir) => source.relative_to(dir),
+            None => source,
+        })
+    }
+
+    pub fn syslib_map_path(&self, config_path: Option<&Path>) -> Option<PathBuf> {
+        Some(config_path?.parent()?.join(self.syslib_map.as_ref()?))
+    }
+
+    /// The database of Debian packages for native libraries, extended by the
+    /// syslib_map file if one is given.
+    pub fn syslib_map(&self, config_path: Option<&Path>) -> Result<SysLibMap> {
+        SysLibMap::load(self.syslib_map_path(config_path).as_deref())
+    }
+
+    pub fn upstream_clone(&self, config_path: Option<&Path>) -> Option<PathBuf> {
+        Some(config_path?.parent()?.join(self.upstream_clone.as_ref()?))
+    }
+
     pub fn orig_tar_excludes(&self) -> Option<&Vec<String>> {
         self.excludes.as_ref()
     }
@@ -114,7 +499,10 @@ impl Config {
     }
 
     pub fn maintainer(&self) -> &str {
-        self.maintainer.as_str()
+        match self.maintainer {
+            Some(ref m) => m,
+            None => &self.selected_profile.maintainer,
+        }
     }
 
     pub fn uploaders(&self) -> Option<&Vec<String>> {
@@ -155,6 +543,24 @@ impl Config {
         self.source.as_ref()?.build_depends_excludes.as_ref()
     }
 
+    pub fn debhelper_compat(&self) -> Option<u32> {
+        self.source.as_ref()?.debhelper_compat
+    }
+
+    /// The Debian architectures to evaluate target-specific dependencies
+    /// against, with any overrides from the config applied.
+    pub fn arch_table(&self) -> Result<ArchTable> {
+        let mut table = ArchTable::default();
+        for (name, arch) in self.architectures.iter().flatten() {
+            if arch.ignore.unwrap_or(false) {
+                table.remove(name);
+            } else {
+                table.set(name, arch.triple.as_deref(), arch.cfg.as_deref())?;
+            }
+        }
+        Ok(table)
+    }
+
     // Packages shortcuts
 
     fn with_package<'a, T, F: FnOnce(&'a PackageOverride) -> Option<T>>(
@@ -207,6 +613,53 @@ impl Config {
     pub fn package_test_depends(&self, key: PackageKey) -> Option<&Vec<String>> {
         self.with_package(key, |pkg| pkg.test_depends.as_ref())
     }
+
+    /// Keys of `packages."lib+FEATURE"` overrides whose feature is not one of
+    /// the given crate features.
+    pub fn stale_feature_overrides(&self, features: &[&str]) -> Vec<&str> {
+        self.packages
+            .iter()
+            .flat_map(|p| p.keys())
+            .map(String::as_str)
+            .filter(|k| match k.strip_prefix("lib+") {
+                Some("@") | None => false,
+                Some(f) => !features.contains(&f),
+            })
+            .collect()
+    }
+}
+
+/// Look up the config for a crate in a directory of configs, such as
+/// debcargo-conf's src/. The config subdirectory is looked up by crate name
+/// and version, from more specific to less specific, e.g. <crate>-1.2.3, then
+/// <crate>-1.2, then <crate>-1 and finally <crate>. The config is merged
+/// over `defaults`, if given.
+pub fn find_config(
+    config_dir: &Path,
+    defaults: Option<&Path>,
+    crate_name: &str,
+    version: &Version,
+) -> Result<(Option<PathBuf>, Config)> {
+    let name = base_deb_name(crate_name);
+    let candidates = [
+        format!(
+            "{}-{}.{}.{}",
+            name, version.major, version.minor, version.patch
+        ),
+        format!("{}-{}.{}", name, version.major, version.minor),
+        format!("{}-{}", name, version.major),
+        name,
+    ];
+    for c in candidates {
+        let path = config_dir.join(c).join("debian").join("debcargo.toml");
+        if path.is_file() {
+            let config = Config::parse_with_defaults(&path, defaults)
+                .context("failed to parse debcargo.toml")?;
+            log::debug!("using config for {} {}: {:?}", crate_name, version, path);
+            return Ok((Some(path), config));
+        }
+    }
+    Ok((None, Config::default()))
 }
 
 pub fn package_field_for_feature<'a>(
@@ -252,7 +705,3 @@ fn package_key_string(key: PackageKey) -> String {
 pub fn testing_ignore_debpolv() -> bool {
     std::env::var("DEBCARGO_TESTING_IGNORE_DEBIAN_POLICY_VIOLATION") == Ok("1".to_string())
 }
-
-pub fn testing_ruzt() -> bool {
-    std::env::var("DEBCARGO_TESTING_RUZT") == Ok("1".to_string())
-}
@@ -10,7 +10,7 @@ use cargo::{
     ops,
     ops::{PackageOpts, Packages},
     sources::RegistrySource,
-    util::{interning::InternedString, toml::read_manifest, FileLock},
+    util::{interning::InternedString, toml::read_manifest, FileLock, Filesystem},
     Config,
 };
 use filetime::{set_file_times, FileTime};
@@ -26,13 +26,19 @@ use std::collections::{BTreeMap, HashSet};
 use std::fs;
 use std::hash::{Hash, Hasher};
 use std::io::{self, Read, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
 use crate::config::testing_ignore_debpolv;
+use crate::debian::arch::ArchTable;
 use crate::errors::*;
 
+pub mod diff;
+pub mod scan;
+
 pub struct CrateInfo {
-    // only used for to_registry_toml in extract_crate. DO NOT USE ELSEWHERE
+    // only used for to_registry_toml in extract_crate, and as the unpatched
+    // manifest in upstream_manifest. DO NOT USE ELSEWHERE
     package: Package,
     // allows overriding package.manifest() e.g. via patches
     manifest: Manifest,
@@ -41,6 +47,7 @@ pub struct CrateInfo {
     source_id: SourceId,
     excludes: Vec<Pattern>,
     includes: Vec<Pattern>,
+    arch_table: ArchTable,
 }
 
 pub type CrateDepInfo = BTreeMap<
@@ -81,10 +88,79 @@ pub fn invalidate_crates_io_cache() -> Result<()> {
     Ok(())
 }
 
-pub fn crate_name_ver_to_dep(crate_name: &str, version: Option<&str>) -> Result<Dependency> {
+/// Where to get crates from.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum CrateSource {
+    /// crates.io, or whatever cargo's config replaces it with.
+    #[default]
+    CratesIo,
+    /// A registry from cargo's config, as for `cargo --registry`.
+    Registry(String),
+    /// A directory created by `cargo local-registry`.
+    LocalRegistry(PathBuf),
+    /// A directory of unpacked crates created by `cargo vendor`.
+    Directory(PathBuf),
+}
+
+impl FromStr for CrateSource {
+    type Err = Error;
+
+    /// Parse `crates-io`, a registry name, `local-registry:PATH` or
+    /// `directory:PATH`.
+    fn from_str(s: &str) -> Result<Self> {
+        let path = |p: &str| {
+            if p.is_empty() {
+                Err(format_err!("missing path in crate source {}", s))
+            } else {
+                Ok(PathBuf::from(p))
+            }
+        };
+        Ok(match s.split_once(':') {
+            Some(("local-registry", p)) => CrateSource::LocalRegistry(path(p)?),
+            Some(("directory", p)) => CrateSource::Directory(path(p)?),
+            Some(_) => debcargo_bail!(
+                "unknown crate source {}; expected crates-io, a registry name, \
+                 local-registry:PATH or directory:PATH",
+                s
+            ),
+            None if s == "crates-io" || s.is_empty() => CrateSource::CratesIo,
+            None => CrateSource::Registry(s.to_string()),
+        })
+    }
+}
+
+impl CrateSource {
+    /// Resolve relative paths against `dir`.
+    pub fn relative_to(self, dir: &Path) -> Self {
+        match self {
+            CrateSource::LocalRegistry(p) => CrateSource::LocalRegistry(dir.join(p)),
+            CrateSource::Directory(p) => CrateSource::Directory(dir.join(p)),
+            s => s,
+        }
+    }
+
+    pub fn source_id(&self, config: &Config) -> Result<SourceId> {
+        let canonicalize = |p: &Path| {
+            p.canonicalize()
+                .map_err(|e| format_err!("crate source {}: {}", p.display(), e))
+        };
+        Ok(match self {
+            CrateSource::CratesIo => SourceId::crates_io(config)?,
+            CrateSource::Registry(name) => SourceId::alt_registry(config, name)?,
+            CrateSource::LocalRegistry(p) => SourceId::for_local_registry(&canonicalize(p)?)?,
+            CrateSource::Directory(p) => SourceId::for_directory(&canonicalize(p)?)?,
+        })
+    }
+}
+
+pub fn crate_name_ver_to_dep(
+    crate_name: &str,
+    version: Option<&str>,
+    source: &CrateSource,
+) -> Result<Dependency> {
     // note: this forces a network call
     let config = Config::default()?;
-    let source_id = SourceId::crates_io(&config)?;
+    let source_id = source.source_id(&config)?;
     let version = version.and_then(|v| {
         if v.is_empty() {
             None
@@ -102,8 +178,8 @@ pub fn show_dep(dep: &Dependency) -> String {
 }
 
 impl CrateInfo {
-    pub fn new(crate_name: &str, version: Option<&str>) -> Result<CrateInfo> {
-        CrateInfo::new_with_update(crate_name, version, true)
+    pub fn new(crate_name: &str, version: Option<&str>, source: &CrateSource) -> Result<CrateInfo> {
+        CrateInfo::new_with_update(crate_name, version, source, true)
     }
 
     pub fn new_with_local_crate(
@@ -115,7 +191,7 @@ impl CrateInfo {
         let crate_path = crate_path.canonicalize()?;
         let source_id = SourceId::for_path(&crate_path)?;
 
-        let (package, crate_file) = {
+        let package = {
             let yanked_whitelist = HashSet::new();
 
             let mut source = source_id.load(&config, &yanked_whitelist)?;
@@ -141,48 +217,53 @@ impl CrateInfo {
             };
 
             let maybe_package = source.download(package_id)?;
-            let package = match maybe_package {
+            match maybe_package {
                 MaybePackage::Ready(p) => Ok(p),
                 _ => Err(format_err!(
                     "Failed to 'download' local crate {} from {}",
                     crate_name,
                     crate_path.display()
                 )),
-            }?;
-
-            let crate_file = {
-                let workspace = Workspace::ephemeral(package.clone(), &config, None, true)?;
-
-                let opts = PackageOpts {
-                    config: &config,
-                    verify: false,
-                    list: false,
-                    check_metadata: true,
-                    allow_dirty: true,
-                    cli_features: CliFeatures::from_command_line(&[], true, false)?,
-                    jobs: None,
-                    targets: Vec::new(),
-                    to_package: Packages::Default,
-                    keep_going: false,
-                };
-
-                // as of cargo 0.41 this returns a FileLock with a temp path, instead of the one
-                // it got renamed to
-                if ops::package(&workspace, &opts)?.is_none() {
-                    return Err(format_err!(
-                        "Failed to assemble crate file for local crate {} at {}\n",
-                        crate_name,
-                        crate_path.display()
-                    ));
-                }
-                let filename = format!("{}-{}.crate", crate_name, package_id.version());
-                workspace
-                    .target_dir()
-                    .join("package")
-                    .open_rw(filename, &config, "crate file")?
+            }?
+        };
+
+        Self::new_with_local_package(package, config)
+    }
+
+    /// Like `new_with_local_crate`, for a package that is already loaded,
+    /// e.g. with a rewritten manifest. It is packaged with `config`.
+    pub fn new_with_local_package(package: Package, config: Config) -> Result<CrateInfo> {
+        let source_id = package.package_id().source_id();
+        let crate_file = {
+            let workspace = Workspace::ephemeral(package.clone(), &config, None, true)?;
+
+            let opts = PackageOpts {
+                config: &config,
+                verify: false,
+                list: false,
+                check_metadata: true,
+                allow_dirty: true,
+                cli_features: CliFeatures::from_command_line(&[], true, false)?,
+                jobs: None,
+                targets: Vec::new(),
+                to_package: Packages::Default,
+                keep_going: false,
             };
 
-            (package, crate_file)
+            // as of cargo 0.41 this returns a FileLock with a temp path, instead of the one
+            // it got renamed to
+            if ops::package(&workspace, &opts)?.is_none() {
+                return Err(format_err!(
+                    "Failed to assemble crate file for local crate {} at {}\n",
+                    package.name(),
+                    package.root().display()
+                ));
+            }
+            let filename = format!("{}-{}.crate", package.name(), package.version());
+            workspace
+                .target_dir()
+                .join("package")
+                .open_rw(filename, &config, "crate file")?
         };
 
         let manifest = package.manifest().clone();
@@ -195,15 +276,17 @@ impl CrateInfo {
             source_id,
             excludes: vec![],
             includes: vec![],
+            arch_table: ArchTable::default(),
         })
     }
 
     pub fn new_with_update(
         crate_name: &str,
         version: Option<&str>,
+        source: &CrateSource,
         update: bool,
     ) -> Result<CrateInfo> {
-        let dep = crate_name_ver_to_dep(crate_name, version)?;
+        let dep = crate_name_ver_to_dep(crate_name, version, source)?;
         Self::new_from_dependency(&dep, update)
     }
 
@@ -271,16 +354,37 @@ impl CrateInfo {
                 }
             }
             let filename = format!("{}-{}.crate", pkgid.name(), pkgid.version());
-            let crate_file = config
-                .registry_cache_path()
-                .join(&registry_name)
-                .open_ro(&filename, config, &filename)?;
-            Ok((package.clone(), manifest.clone(), crate_file))
+            let crate_dir = if source_id.is_remote_registry() {
+                config.registry_cache_path().join(&registry_name)
+            } else if source_id.is_registry() {
+                // a local registry keeps its .crate files in its top level
+                let root = source_id.url().to_file_path().map_err(|_| {
+                    format_err!("local registry {} is not a local path", source_id.url())
+                })?;
+                Filesystem::new(root)
+            } else {
+                // a directory source only has unpacked crates
+                return Ok((package.clone(), manifest.clone(), None));
+            };
+            let crate_file = crate_dir.open_ro(&filename, config, &filename)?;
+            Ok((package.clone(), manifest.clone(), Some(crate_file)))
         };
         // if update is false but the user never downloaded the crate then the
         // first call will error; re-try with online in that case
         let (package, manifest, crate_file) =
             get_package_info(&config).or_else(|_| get_package_info(&Config::default()?))?;
+        let crate_file = match crate_file {
+            Some(f) => f,
+            None => {
+                // package it like any other local crate
+                let version = package.version().to_string();
+                return Self::new_with_local_crate(
+                    package.name().as_str(),
+                    Some(&version),
+                    package.root(),
+                );
+            }
+        };
 
         Ok(CrateInfo {
             package,
@@ -290,9 +394,16 @@ impl CrateInfo {
             source_id,
             excludes: vec![],
             includes: vec![],
+            arch_table: ArchTable::default(),
         })
     }
 
+    /// Where the crate came from. This is a path source for local crates,
+    /// including ones from a `directory:` crate source.
+    pub fn source_id(&self) -> SourceId {
+        self.source_id
+    }
+
     pub fn crate_name(&self) -> &'static str {
         self.package_id().name().as_str()
     }
@@ -321,6 +432,12 @@ impl CrateInfo {
         Ok(self)
     }
 
+    /// The manifest as published, without the patches that may have been
+    /// applied by `replace_manifest`.
+    pub fn upstream_manifest(&self) -> &Manifest {
+        self.package.manifest()
+    }
+
     pub fn metadata(&self) -> &ManifestMetadata {
         self.manifest.metadata()
     }
@@ -337,6 +454,12 @@ impl CrateInfo {
         self.manifest.targets()
     }
 
+    /// The native library that the crate links to, from `links` in
+    /// Cargo.toml.
+    pub fn links(&self) -> Option<&str> {
+        self.manifest.links()
+    }
+
     pub fn is_lib(&self) -> bool {
         let mut lib = false;
         for target in self.manifest.targets() {
@@ -381,15 +504,76 @@ impl CrateInfo {
         &self.crate_file
     }
 
+    /// Whether cargo is configured not to access the network.
+    pub fn offline(&self) -> bool {
+        self.config.offline()
+    }
+
+    /// The entries for all versions of this crate in the local copy of the
+    /// registry index: cargo's cache of it for a remote registry, or the
+    /// index of a `cargo local-registry` directory. Empty if there is none,
+    /// e.g. for a local crate.
+    pub fn local_index_entries(&self) -> Vec<serde_json::Value> {
+        let name = self.crate_name().to_lowercase();
+        let prefix = match name.len() {
+            1 => "1".to_string(),
+            2 => "2".to_string(),
+            3 => format!("3/{}", &name[..1]),
+            _ => format!("{}/{}", &name[..2], &name[2..4]),
+        };
+        let dir = match self.crate_file.path().parent() {
+            Some(dir) => dir,
+            None => return vec![],
+        };
+        // .crate files from a remote registry are in registry/cache/<name>,
+        // and its cached index is in registry/index/<name>/.cache
+        let index = match (dir.parent(), dir.file_name()) {
+            (Some(cache), Some(registry)) if cache.ends_with("registry/cache") => {
+                cache.with_file_name("index").join(registry).join(".cache")
+            }
+            _ => dir.join("index"),
+        };
+        let data = match fs::read(index.join(prefix).join(&name)) {
+            Ok(data) => data,
+            Err(_) => return vec![],
+        };
+        // cargo's cache has a binary header and separates entries and their
+        // versions with NUL bytes; a local registry has one entry per line
+        data.split(|&b| b == b'\0' || b == b'\n')
+            .filter(|e| e.starts_with(b"{"))
+            .filter_map(|e| serde_json::from_slice(e).ok())
+            .collect()
+    }
+
     pub fn dependencies(&self) -> &[Dependency] {
         self.manifest.dependencies()
     }
 
+    /// Whether a dependency applies on any Debian architecture, i.e. it is
+    /// not specific to some other target such as Windows.
+    pub fn is_debian_dependency(&self, dep: &Dependency) -> bool {
+        self.arch_table.matches_any(dep.platform())
+    }
+
+    /// Dependencies that do not apply on any Debian architecture.
+    pub fn non_debian_dependencies(&self) -> Vec<&Dependency> {
+        self.dependencies()
+            .iter()
+            .filter(|d| !self.is_debian_dependency(d))
+            .collect()
+    }
+
+    /// The Debian architectures that a dependency applies on, or None if it
+    /// applies on all of them.
+    pub fn dependency_arches(&self, dep: &Dependency) -> Option<Vec<&str>> {
+        self.arch_table.restriction(dep.platform())
+    }
+
     pub fn dev_dependencies(&self) -> Vec<Dependency> {
         use cargo::core::dependency::DepKind;
         let mut deps = vec![];
         for dep in self.dependencies() {
-            if dep.kind() == DepKind::Development {
+            if dep.kind() == DepKind::Development && self.is_debian_dependency(dep) {
                 deps.push(dep.clone())
             }
         }
@@ -428,7 +612,9 @@ impl CrateInfo {
                     Dep { dep_name } => {
                         // unwrap is ok, valid Cargo.toml files must have this
                         for &dep in deps_by_name.get(dep_name.as_str()).unwrap() {
-                            other_deps.push(dep.clone());
+                            if self.is_debian_dependency(dep) {
+                                other_deps.push(dep.clone());
+                            }
                         }
                     }
                     // another package is a dependency
@@ -439,7 +625,7 @@ impl CrateInfo {
                     } => match deps_by_name.get(dep_name.as_str()) {
                         // unwrap is ok, valid Cargo.toml files must have this
                         Some(dd) => {
-                            for &dep in dd {
+                            for &dep in dd.iter().filter(|d| self.is_debian_dependency(d)) {
                                 let mut dep = dep.clone();
                                 let mut features: Vec<InternedString> =
                                     vec![InternedString::new(dep_feature)];
@@ -488,7 +674,7 @@ impl CrateInfo {
         let mut deps_required: Vec<Dependency> = Vec::new();
         for deps in deps_by_name.values() {
             for &dep in deps {
-                if !dep.is_optional() {
+                if !dep.is_optional() && self.is_debian_dependency(dep) {
                     deps_required.push(dep.clone())
                 }
             }
@@ -578,30 +764,19 @@ impl CrateInfo {
             .collect::<Vec<_>>();
     }
 
-    pub fn filter_path(&self, path: &Path) -> ::std::result::Result<bool, String> {
-        if self.excludes.iter().any(|p| p.matches_path(path)) {
-            return Ok(true);
-        }
-        let suspicious = match path.extension() {
-            Some(ext) => ext == "c" || ext == "a",
-            _ => false,
-        };
-        if suspicious {
-            if self.includes.iter().any(|p| p.matches_path(path)) {
-                debcargo_info!("Suspicious file, on whitelist so ignored: {:?}", path);
-                Ok(false)
-            } else if testing_ignore_debpolv() {
-                debcargo_warn!("Suspicious file, ignoring as per override: {:?}", path);
-                Ok(false)
-            } else {
-                Err(format!(
-                    "Suspicious file, should probably be excluded: {:?}",
-                    path
-                ))
-            }
-        } else {
-            Ok(false)
-        }
+    pub fn set_arch_table(&mut self, arch_table: ArchTable) {
+        self.arch_table = arch_table;
+    }
+
+    /// Whether a path in the crate tarball is excluded from the orig tarball.
+    pub fn is_excluded(&self, path: &Path) -> bool {
+        self.excludes.iter().any(|p| p.matches_path(path))
+    }
+
+    /// Whether a path in the crate tarball has been manually reviewed, so it
+    /// is not checked for prebuilt binaries and the like.
+    pub fn is_whitelisted(&self, path: &Path) -> bool {
+        self.includes.iter().any(|p| p.matches_path(path))
     }
 
     pub fn extract_crate(&self, path: &Path) -> Result<bool> {
@@ -611,34 +786,65 @@ impl CrateInfo {
             .tempdir_in(".")?;
         let mut source_modified = false;
         let mut last_mtime = 0;
-        let mut err = vec![];
+        let mut suspicious = vec![];
 
         for entry in archive.entries()? {
             let mut entry = entry?;
-            match self.filter_path(&(entry.path()?)) {
-                Err(e) => err.push(e),
-                Ok(r) => {
-                    if r {
-                        source_modified = true;
-                        continue;
-                    }
-                }
+            let entry_path = entry.path()?.into_owned();
+            if self.is_excluded(&entry_path) {
+                source_modified = true;
+                continue;
             }
 
             if !entry.unpack_in(tempdir.path())? {
                 debcargo_bail!("Crate contained path traversals via '..'");
             }
 
+            let unpacked = tempdir.path().join(&entry_path);
+            if entry.header().entry_type().is_file() {
+                if let Some(reason) = scan::scan(&entry_path, &fs::read(&unpacked)?) {
+                    if self.is_whitelisted(&entry_path) {
+                        debcargo_info!(
+                            "Suspicious file, on whitelist so ignored: {:?}: {}",
+                            entry_path,
+                            reason
+                        );
+                    } else if testing_ignore_debpolv() {
+                        debcargo_warn!(
+                            "Suspicious file, ignoring as per override: {:?}: {}",
+                            entry_path,
+                            reason
+                        );
+                    } else {
+                        suspicious.push((entry_path, reason));
+                    }
+                }
+            }
+
             if let Ok(mtime) = entry.header().mtime() {
                 if mtime > last_mtime {
                     last_mtime = mtime;
                 }
             }
         }
-        if !err.is_empty() {
-            for e in err {
-                debcargo_warn!("{}", e);
+        if !suspicious.is_empty() {
+            for (path, reason) in &suspicious {
+                debcargo_warn!(
+                    "Suspicious file, should probably be excluded: {:?}: {}",
+                    path,
+                    reason
+                );
             }
+            // excludes are matched below the top-level directory
+            let paths = suspicious
+                .iter()
+                .map(|(p, _)| p.components().skip(1).collect::<PathBuf>())
+                .collect::<Vec<_>>();
+            debcargo_warn!(
+                "To exclude them, add this to debcargo.toml, or add them to whitelist \
+                 if they are fine:\n{}",
+                scan::excludes_snippet(paths.iter().map(PathBuf::as_path))
+            );
             debcargo_bail!(
                 "Suspicious files detected, aborting. Ask on #debian-rust if you are stuck."
             )
@@ -0,0 +1,217 @@
+//! What changed in a crate between two versions, as far as its Debian
+//! packaging is concerned, for summarizing in debian/changelog.
+
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet};
+
+use cargo::core::{dependency::DepKind, Dependency};
+
+use super::CrateInfo;
+use crate::errors::*;
+
+/// The parts of a crate's manifest that matter to its packaging.
+#[derive(Debug, Clone, Default)]
+pub struct ManifestFacts {
+    pub features: BTreeSet<String>,
+    /// Non-dev dependencies that apply in Debian, with their version
+    /// requirements.
+    pub dependencies: BTreeMap<String, String>,
+    pub rust_version: Option<String>,
+    pub binaries: BTreeSet<String>,
+}
+
+impl ManifestFacts {
+    /// The facts for the crate as published, ignoring any patches from the
+    /// overlay, so that versions of it are compared like for like.
+    pub fn from_crate(crate_info: &CrateInfo) -> Self {
+        let manifest = crate_info.upstream_manifest();
+        let mut dependencies: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for dep in manifest.dependencies() {
+            if dep.kind() == DepKind::Development || !crate_info.is_debian_dependency(dep) {
+                continue;
+            }
+            let reqs = dependencies
+                .entry(dep.package_name().to_string())
+                .or_default();
+            let req = dep.version_req().to_string();
+            if !reqs.contains(&req) {
+                reqs.push(req);
+            }
+        }
+        ManifestFacts {
+            features: manifest
+                .summary()
+                .features()
+                .keys()
+                .map(|f| f.to_string())
+                .collect(),
+            dependencies: dependencies
+                .into_iter()
+                .map(|(name, reqs)| (name, reqs.join(", ")))
+                .collect(),
+            rust_version: manifest.rust_version().map(String::from),
+            binaries: manifest
+                .targets()
+                .iter()
+                .filter(|t| t.is_bin())
+                .map(|t| t.name().to_string())
+                .collect(),
+        }
+    }
+}
+
+/// The differences between two `ManifestFacts`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ManifestDiff {
+    pub added_features: Vec<String>,
+    pub removed_features: Vec<String>,
+    pub added_dependencies: Vec<(String, String)>,
+    pub removed_dependencies: Vec<String>,
+    /// (name, old requirement, new requirement)
+    pub changed_dependencies: Vec<(String, String, String)>,
+    /// The old and new rust-version, if it changed.
+    pub rust_version: Option<(Option<String>, Option<String>)>,
+    pub added_binaries: Vec<String>,
+    pub removed_binaries: Vec<String>,
+}
+
+fn compare_rust_versions(a: &str, b: &str) -> Ordering {
+    let parts = |v: &str| {
+        v.split('.')
+            .map(|p| p.parse::<u64>().unwrap_or(0))
+            .collect::<Vec<_>>()
+    };
+    parts(a).cmp(&parts(b))
+}
+
+impl ManifestDiff {
+    pub fn new(old: &ManifestFacts, new: &ManifestFacts) -> Self {
+        let difference = |a: &BTreeSet<String>, b: &BTreeSet<String>| {
+            a.difference(b).cloned().collect::<Vec<_>>()
+        };
+        let mut diff = ManifestDiff {
+            added_features: difference(&new.features, &old.features),
+            removed_features: difference(&old.features, &new.features),
+            added_binaries: difference(&new.binaries, &old.binaries),
+            removed_binaries: difference(&old.binaries, &new.binaries),
+            ..Default::default()
+        };
+        for (name, req) in &new.dependencies {
+            match old.dependencies.get(name) {
+                None => diff.added_dependencies.push((name.clone(), req.clone())),
+                Some(old_req) if old_req != req => {
+                    diff.changed_dependencies
+                        .push((name.clone(), old_req.clone(), req.clone()))
+                }
+                Some(_) => (),
+            }
+        }
+        diff.removed_dependencies = old
+            .dependencies
+            .keys()
+            .filter(|name| !new.dependencies.contains_key(*name))
+            .cloned()
+            .collect();
+        if old.rust_version != new.rust_version {
+            diff.rust_version = Some((old.rust_version.clone(), new.rust_version.clone()));
+        }
+        diff
+    }
+
+    /// Items for debian/changelog, one per change.
+    pub fn changelog_items(&self) -> Vec<String> {
+        let mut items = Vec::new();
+        for f in &self.added_features {
+            items.push(format!("  * New feature \"{}\"", f));
+        }
+        for f in &self.removed_features {
+            items.push(format!("  * Removed feature \"{}\"", f));
+        }
+        for (name, req) in &self.added_dependencies {
+            items.push(format!("  * New dependency on {} {}", name, req));
+        }
+        for name in &self.removed_dependencies {
+            items.push(format!("  * Dropped dependency on {}", name));
+        }
+        for (name, old, new) in &self.changed_dependencies {
+            items.push(format!(
+                "  * Bumped dependency on {} from {} to {}",
+                name, old, new
+            ));
+        }
+        match &self.rust_version {
+            Some((Some(old), Some(new))) => {
+                let verb = match compare_rust_versions(old, new) {
+                    Ordering::Greater => "Lowered",
+                    _ => "Raised",
+                };
+                items.push(format!("  * {} MSRV from {} to {}", verb, old, new));
+            }
+            Some((None, Some(new))) => items.push(format!("  * Set MSRV to {}", new)),
+            Some((Some(old), None)) => items.push(format!("  * Dropped MSRV of {}", old)),
+            _ => (),
+        }
+        for b in &self.added_binaries {
+            items.push(format!("  * New binary {}", b));
+        }
+        for b in &self.removed_binaries {
+            items.push(format!("  * Dropped binary {}", b));
+        }
+        items
+    }
+}
+
+/// Load another version of a crate from the same source, given its Debian
+/// upstream version. Returns None for local crates, which have only one
+/// version. The version is downloaded unless it is in the cargo cache, so
+/// this fails when offline if it is not.
+pub fn load_version(crate_info: &CrateInfo, deb_version: &str) -> Result<Option<CrateInfo>> {
+    let source_id = crate_info.source_id();
+    if source_id.is_path() {
+        return Ok(None);
+    }
+    let version = format!("={}", deb_version.replace('~', "-"));
+    let dep = Dependency::parse(crate_info.crate_name(), Some(&version), source_id)?;
+    Ok(Some(CrateInfo::new_from_dependency(&dep, false)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ManifestDiff, ManifestFacts};
+
+    #[test]
+    fn summarize_changes() {
+        let set = |v: &[&str]| v.iter().map(|s| s.to_string()).collect();
+        let map = |v: &[(&str, &str)]| {
+            v.iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+        let old = ManifestFacts {
+            features: set(&["default", "std", "unstable"]),
+            dependencies: map(&[("libc", "^0.2"), ("log", "^0.4"), ("rand", "^0.7")]),
+            rust_version: Some("1.56".into()),
+            binaries: set(&["foo"]),
+        };
+        let new = ManifestFacts {
+            features: set(&["default", "std", "serde"]),
+            dependencies: map(&[("libc", "^0.2"), ("rand", "^0.8"), ("serde", "^1.0")]),
+            rust_version: Some("1.60".into()),
+            binaries: set(&["foo", "foo-cli"]),
+        };
+        let items = ManifestDiff::new(&old, &new).changelog_items();
+        assert_eq!(
+            items,
+            [
+                "  * New feature \"serde\"",
+                "  * Removed feature \"unstable\"",
+                "  * New dependency on serde ^1.0",
+                "  * Dropped dependency on log",
+                "  * Bumped dependency on rand from ^0.7 to ^0.8",
+                "  * Raised MSRV from 1.56 to 1.60",
+                "  * New binary foo-cli",
+            ]
+        );
+        assert!(ManifestDiff::new(&new, &new).changelog_items().is_empty());
+    }
+}
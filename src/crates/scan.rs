@@ -0,0 +1,187 @@
+//! Content-aware checks for files that Debian policy does not allow in a
+//! source package without their source, such as prebuilt binaries and
+//! minified code.
+
+use std::fmt;
+use std::path::Path;
+
+/// Why a file is suspicious.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reason {
+    /// The file contents start with the magic bytes of this kind of binary.
+    Magic(&'static str),
+    /// The file name has an extension of this kind of file.
+    Extension(&'static str),
+    /// The file looks like minified JavaScript or CSS.
+    Minified,
+    /// The file is a binary file of this many bytes.
+    LargeBlob(u64),
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Reason::Magic(kind) => write!(f, "{}", kind),
+            Reason::Extension(kind) => write!(f, "{} (by file name)", kind),
+            Reason::Minified => write!(f, "minified code"),
+            Reason::LargeBlob(size) => write!(f, "large binary blob of {} bytes", size),
+        }
+    }
+}
+
+const MAGIC: &[(&[u8], &str)] = &[
+    (b"\x7fELF", "ELF binary"),
+    (b"!<arch>\n", "static library or rlib"),
+    (b"\0asm", "WebAssembly binary"),
+    (b"\xca\xfe\xba\xbe", "Java class or Mach-O universal binary"),
+    (b"\xfe\xed\xfa\xce", "Mach-O binary"),
+    (b"\xfe\xed\xfa\xcf", "Mach-O binary"),
+    (b"\xce\xfa\xed\xfe", "Mach-O binary"),
+    (b"\xcf\xfa\xed\xfe", "Mach-O binary"),
+    (b"PK\x03\x04", "zip archive, e.g. a .jar"),
+];
+
+const EXTENSIONS: &[(&str, &str)] = &[
+    ("a", "static library"),
+    ("c", "C source, possibly a bundled copy of a library"),
+    ("class", "Java class"),
+    ("dll", "Windows DLL"),
+    ("dylib", "macOS shared library"),
+    ("exe", "Windows executable"),
+    ("jar", "Java archive"),
+    ("lib", "Windows static library"),
+    ("node", "Node.js native module"),
+    ("o", "object file"),
+    ("obj", "object file"),
+    ("pyc", "compiled Python"),
+    ("rlib", "Rust library"),
+    ("so", "shared library"),
+    ("wasm", "WebAssembly binary"),
+];
+
+/// Binary files up to this size are assumed to be test data and the like.
+const LARGE_BLOB: u64 = 1 << 20;
+/// Lines in JavaScript or CSS longer than this suggest minified code.
+const MINIFIED_LINE: usize = 500;
+
+fn is_pe(content: &[u8]) -> bool {
+    // the MZ header points to the PE header at offset 0x3c
+    if !content.starts_with(b"MZ") || content.len() < 0x40 {
+        return false;
+    }
+    let offset = u32::from_le_bytes([content[0x3c], content[0x3d], content[0x3e], content[0x3f]]);
+    let offset = offset as usize;
+    content.get(offset..offset + 4) == Some(b"PE\0\0")
+}
+
+fn is_binary(content: &[u8]) -> bool {
+    content.iter().take(8192).any(|&b| b == 0)
+}
+
+fn is_minified(path: &Path, content: &[u8]) -> bool {
+    let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+    if !(name.ends_with(".js") || name.ends_with(".mjs") || name.ends_with(".css")) {
+        return false;
+    }
+    if name.ends_with(".min.js") || name.ends_with(".min.css") {
+        return true;
+    }
+    content
+        .split(|&b| b == b'\n')
+        .any(|l| l.len() > MINIFIED_LINE)
+}
+
+/// Check a file from a crate, given its path and contents.
+pub fn scan(path: &Path, content: &[u8]) -> Option<Reason> {
+    if let Some((_, kind)) = MAGIC.iter().find(|(m, _)| content.starts_with(m)) {
+        return Some(Reason::Magic(kind));
+    }
+    if is_pe(content) {
+        return Some(Reason::Magic("Windows PE binary"));
+    }
+    let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+    // versioned shared libraries like libfoo.so.1 have no .so extension
+    let ext = match name.split_once(".so.") {
+        Some(_) => Some("so"),
+        None => path.extension().and_then(|e| e.to_str()),
+    };
+    if let Some((_, kind)) = EXTENSIONS.iter().find(|(e, _)| Some(*e) == ext) {
+        return Some(Reason::Extension(kind));
+    }
+    if is_minified(path, content) {
+        return Some(Reason::Minified);
+    }
+    let size = content.len() as u64;
+    if size > LARGE_BLOB && is_binary(content) {
+        return Some(Reason::LargeBlob(size));
+    }
+    None
+}
+
+/// A snippet for debcargo.toml that excludes the given paths, which are
+/// relative to the top-level directory of the crate. Since excludes are
+/// globs, any glob syntax in the paths is escaped.
+pub fn excludes_snippet<'a>(paths: impl IntoIterator<Item = &'a Path>) -> String {
+    let mut snippet = "excludes = [\n".to_string();
+    for path in paths {
+        let pattern = glob::Pattern::escape(&path.to_string_lossy());
+        snippet.push_str(&format!("    {},\n", toml::Value::String(pattern)));
+    }
+    snippet.push(']');
+    snippet
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{excludes_snippet, scan, Reason};
+    use std::path::Path;
+
+    #[test]
+    fn suspicious_files() {
+        let check = |path: &str, content: &[u8]| scan(Path::new(path), content);
+        assert_eq!(
+            check("vendor/lib/x", b"\x7fELF\x02\x01\x01"),
+            Some(Reason::Magic("ELF binary"))
+        );
+        let mut pe = vec![0; 0x80];
+        pe[..2].copy_from_slice(b"MZ");
+        pe[0x3c] = 0x40;
+        pe[0x40..0x44].copy_from_slice(b"PE\0\0");
+        assert_eq!(
+            check("tool.bin", &pe),
+            Some(Reason::Magic("Windows PE binary"))
+        );
+        assert_eq!(check("MZ.txt", b"MZ is a text file"), None);
+        assert!(matches!(
+            check("libfoo.so.1", b""),
+            Some(Reason::Extension(_))
+        ));
+        assert!(matches!(
+            check("src/foo.c", b"int x;"),
+            Some(Reason::Extension(_))
+        ));
+
+        let long_line = vec![b'a'; 600];
+        assert_eq!(check("web/app.js", &long_line), Some(Reason::Minified));
+        assert_eq!(check("web/app.min.css", b"a{}"), Some(Reason::Minified));
+        assert_eq!(check("data/words.txt", &long_line), None);
+        assert_eq!(check("src/lib.rs", b"fn main() {}\n"), None);
+
+        let mut blob = vec![0; 2 << 20];
+        assert_eq!(
+            check("tests/data.bin", &blob),
+            Some(Reason::LargeBlob(2 << 20))
+        );
+        blob.truncate(1024);
+        assert_eq!(check("tests/data.bin", &blob), None);
+
+        assert_eq!(
+            excludes_snippet([Path::new("a/b.so"), Path::new("c.js")]),
+            "excludes = [\n    \"a/b.so\",\n    \"c.js\",\n]"
+        );
+        assert_eq!(
+            excludes_snippet([Path::new("dist/[min]*.js"), Path::new("a \"b\".so")]),
+            "excludes = [\n    \"dist/[[]min[]][*].js\",\n    \"a \\\"b\\\".so\",\n]"
+        );
+    }
+}
@@ -0,0 +1,267 @@
+//! Debian architectures, and the Rust targets they correspond to, used to
+//! decide which target-specific dependencies of a crate apply in Debian.
+
+use std::str::FromStr;
+
+use cargo_platform::{Cfg, Platform};
+
+use crate::errors::*;
+
+/// Debian architectures that Rust is built for, as (Debian architecture,
+/// Rust target triple, target_arch, target_endian, target_pointer_width).
+const DEBIAN_ARCHES: &[(&str, &str, &str, &str, &str)] = &[
+    (
+        "amd64",
+        "x86_64-unknown-linux-gnu",
+        "x86_64",
+        "little",
+        "64",
+    ),
+    (
+        "arm64",
+        "aarch64-unknown-linux-gnu",
+        "aarch64",
+        "little",
+        "64",
+    ),
+    (
+        "armel",
+        "armv5te-unknown-linux-gnueabi",
+        "arm",
+        "little",
+        "32",
+    ),
+    (
+        "armhf",
+        "armv7-unknown-linux-gnueabihf",
+        "arm",
+        "little",
+        "32",
+    ),
+    ("i386", "i686-unknown-linux-gnu", "x86", "little", "32"),
+    (
+        "loong64",
+        "loongarch64-unknown-linux-gnu",
+        "loongarch64",
+        "little",
+        "64",
+    ),
+    (
+        "mips64el",
+        "mips64el-unknown-linux-gnuabi64",
+        "mips64",
+        "little",
+        "64",
+    ),
+    (
+        "powerpc",
+        "powerpc-unknown-linux-gnu",
+        "powerpc",
+        "big",
+        "32",
+    ),
+    (
+        "ppc64",
+        "powerpc64-unknown-linux-gnu",
+        "powerpc64",
+        "big",
+        "64",
+    ),
+    (
+        "ppc64el",
+        "powerpc64le-unknown-linux-gnu",
+        "powerpc64",
+        "little",
+        "64",
+    ),
+    (
+        "riscv64",
+        "riscv64gc-unknown-linux-gnu",
+        "riscv64",
+        "little",
+        "64",
+    ),
+    ("s390x", "s390x-unknown-linux-gnu", "s390x", "big", "64"),
+    (
+        "sparc64",
+        "sparc64-unknown-linux-gnu",
+        "sparc64",
+        "big",
+        "64",
+    ),
+    (
+        "x32",
+        "x86_64-unknown-linux-gnux32",
+        "x86_64",
+        "little",
+        "32",
+    ),
+];
+
+/// cfg values that every architecture in `DEBIAN_ARCHES` has.
+const LINUX_CFG: &[&str] = &[
+    "unix",
+    "target_family = \"unix\"",
+    "target_os = \"linux\"",
+    "target_env = \"gnu\"",
+    "target_vendor = \"unknown\"",
+];
+
+#[derive(Debug, Clone)]
+pub struct DebArch {
+    pub name: String,
+    pub triple: String,
+    pub cfg: Vec<Cfg>,
+}
+
+/// The Debian architectures to evaluate target-specific dependencies against.
+#[derive(Debug, Clone)]
+pub struct ArchTable {
+    arches: Vec<DebArch>,
+}
+
+fn parse_cfg(arch: &str, cfg: &str) -> Result<Cfg> {
+    Cfg::from_str(cfg).map_err(|e| format_err!("bad cfg for architecture {}: {}", arch, e))
+}
+
+impl Default for ArchTable {
+    fn default() -> Self {
+        let arches = DEBIAN_ARCHES
+            .iter()
+            .map(|&(name, triple, arch, endian, width)| {
+                let cfg = LINUX_CFG
+                    .iter()
+                    .map(|c| c.to_string())
+                    .chain([
+                        format!("target_arch = \"{}\"", arch),
+                        format!("target_endian = \"{}\"", endian),
+                        format!("target_pointer_width = \"{}\"", width),
+                    ])
+                    .map(|c| parse_cfg(name, &c).unwrap())
+                    .collect();
+                DebArch {
+                    name: name.to_string(),
+                    triple: triple.to_string(),
+                    cfg,
+                }
+            })
+            .collect();
+        ArchTable { arches }
+    }
+}
+
+impl ArchTable {
+    /// Change or add an architecture. A new architecture needs both a triple
+    /// and its cfg values.
+    pub fn set(&mut self, name: &str, triple: Option<&str>, cfg: Option<&[String]>) -> Result<()> {
+        let cfg = match cfg {
+            None => None,
+            Some(cfg) => Some(
+                cfg.iter()
+                    .map(|c| parse_cfg(name, c))
+                    .collect::<Result<Vec<_>>>()?,
+            ),
+        };
+        match self.arches.iter_mut().find(|a| a.name == name) {
+            Some(arch) => {
+                if let Some(triple) = triple {
+                    arch.triple = triple.to_string();
+                }
+                if let Some(cfg) = cfg {
+                    arch.cfg = cfg;
+                }
+            }
+            None => match (triple, cfg) {
+                (Some(triple), Some(cfg)) => self.arches.push(DebArch {
+                    name: name.to_string(),
+                    triple: triple.to_string(),
+                    cfg,
+                }),
+                _ => debcargo_bail!(
+                    "architecture {} is not built in, so it needs both triple and cfg",
+                    name
+                ),
+            },
+        }
+        Ok(())
+    }
+
+    /// Stop considering an architecture.
+    pub fn remove(&mut self, name: &str) {
+        self.arches.retain(|a| a.name != name);
+    }
+
+    /// Whether a dependency for `platform` applies on any architecture.
+    pub fn matches_any(&self, platform: Option<&Platform>) -> bool {
+        match platform {
+            None => true,
+            Some(p) => self.arches.iter().any(|a| p.matches(&a.triple, &a.cfg)),
+        }
+    }
+
+    /// The architectures that a dependency for `platform` applies on, or None
+    /// if it applies on all of them.
+    pub fn restriction(&self, platform: Option<&Platform>) -> Option<Vec<&str>> {
+        let platform = platform?;
+        let arches = self
+            .arches
+            .iter()
+            .filter(|a| platform.matches(&a.triple, &a.cfg))
+            .map(|a| a.name.as_str())
+            .collect::<Vec<_>>();
+        if arches.len() == self.arches.len() {
+            None
+        } else {
+            Some(arches)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ArchTable;
+    use cargo_platform::Platform;
+
+    #[test]
+    fn target_specific_dependencies() {
+        let mut table = ArchTable::default();
+        let restriction = |table: &ArchTable, p: &str| {
+            let p = p.parse::<Platform>().unwrap();
+            table.restriction(Some(&p)).map(|a| a.join(" "))
+        };
+        assert_eq!(restriction(&table, "cfg(unix)"), None);
+        assert_eq!(restriction(&table, "cfg(windows)"), Some("".into()));
+        assert_eq!(
+            restriction(&table, "cfg(target_arch = \"x86_64\")"),
+            Some("amd64 x32".into())
+        );
+        assert_eq!(
+            restriction(
+                &table,
+                "cfg(all(target_arch = \"x86_64\", target_pointer_width = \"64\"))"
+            ),
+            Some("amd64".into())
+        );
+        assert_eq!(
+            restriction(&table, "aarch64-unknown-linux-gnu"),
+            Some("arm64".into())
+        );
+        assert!(!table.matches_any(Some(&"cfg(target_os = \"macos\")".parse().unwrap())));
+
+        table.remove("x32");
+        table
+            .set(
+                "hurd-amd64",
+                Some("x86_64-unknown-hurd-gnu"),
+                Some(&["unix".into(), "target_os = \"hurd\"".into()]),
+            )
+            .unwrap();
+        assert_eq!(
+            restriction(&table, "cfg(not(target_os = \"linux\"))"),
+            Some("hurd-amd64".into())
+        );
+        assert!(table
+            .set("alpha", Some("alpha-unknown-linux-gnu"), None)
+            .is_err());
+    }
+}
@@ -0,0 +1,137 @@
+//! Changes in the binary packages of a source package, which send it through
+//! the NEW queue of the Debian archive when packages are added.
+
+use std::collections::BTreeSet;
+
+use crate::util;
+
+/// The binary packages in a debian/control file, and what they provide.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ControlPackages {
+    pub packages: BTreeSet<String>,
+    pub provides: BTreeSet<String>,
+}
+
+impl ControlPackages {
+    pub fn parse(control: &str) -> Self {
+        let mut result = ControlPackages::default();
+        for para in util::parse_deb822(control) {
+            let package = match para.get("Package") {
+                Some(p) => p,
+                None => continue,
+            };
+            result.packages.insert(package.clone());
+            let provides = para.get("Provides").map(String::as_str).unwrap_or("");
+            for p in provides.split(',') {
+                let name = p.split('(').next().unwrap().trim();
+                // substitution variables are only expanded at build time
+                if !name.is_empty() && !name.starts_with("${") {
+                    result.provides.insert(name.to_string());
+                }
+            }
+        }
+        result
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PackageChanges {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub added_provides: Vec<String>,
+    pub removed_provides: Vec<String>,
+}
+
+/// The crate feature that a binary package is for, if any.
+fn feature_of(package: &str) -> Option<&str> {
+    package.split_once('+')?.1.strip_suffix("-dev")
+}
+
+impl PackageChanges {
+    pub fn new(old: &ControlPackages, new: &ControlPackages) -> Self {
+        let difference = |a: &BTreeSet<String>, b: &BTreeSet<String>| {
+            a.difference(b).cloned().collect::<Vec<_>>()
+        };
+        PackageChanges {
+            added: difference(&new.packages, &old.packages),
+            removed: difference(&old.packages, &new.packages),
+            added_provides: difference(&new.provides, &old.provides),
+            removed_provides: difference(&old.provides, &new.provides),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// Whether the package will have to go through NEW.
+    pub fn needs_new(&self) -> bool {
+        !self.added.is_empty()
+    }
+
+    /// Lines describing the changes, with suggestions for avoiding NEW.
+    pub fn report(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        for p in &self.added {
+            lines.push(format!(
+                "New binary package {}, which needs to go through NEW",
+                p
+            ));
+        }
+        for p in &self.removed {
+            lines.push(format!("Binary package {} is no longer built", p));
+        }
+        for p in &self.added_provides {
+            lines.push(format!("New Provides: {}", p));
+        }
+        for p in &self.removed_provides {
+            lines.push(format!("No longer Provides: {}", p));
+        }
+        let features = self
+            .added
+            .iter()
+            .filter_map(|p| feature_of(p))
+            .collect::<Vec<_>>();
+        if !features.is_empty() {
+            lines.push(format!(
+                "To avoid NEW, either set collapse_features = true in debcargo.toml, or \
+                 patch Cargo.toml so that the features ({}) have no dependencies of their \
+                 own, which puts them in the Provides of an existing package",
+                features.join(", ")
+            ));
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ControlPackages, PackageChanges};
+
+    #[test]
+    fn binary_package_changes() {
+        let old = ControlPackages::parse(
+            "Source: rust-foo\n\n\
+             Package: librust-foo-dev\n\
+             Provides:\n librust-foo+default-dev (= ${binary:Version}),\n \
+             librust-foo+std-dev (= ${binary:Version}),\n ${cargo:Provides}\n\n\
+             Package: librust-foo+serde-dev\n",
+        );
+        let new = ControlPackages::parse(
+            "Source: rust-foo\n\n\
+             # a comment\n\
+             Package: librust-foo-dev\n\
+             Provides:\n librust-foo+default-dev (= ${binary:Version})\n\n\
+             Package: librust-foo+serde-dev\n\n\
+             Package: librust-foo+tokio-dev\n",
+        );
+        assert_eq!(new.packages.len(), 3);
+        let changes = PackageChanges::new(&old, &new);
+        assert_eq!(changes.added, ["librust-foo+tokio-dev"]);
+        assert!(changes.removed.is_empty());
+        assert_eq!(changes.removed_provides, ["librust-foo+std-dev"]);
+        assert!(changes.needs_new());
+        assert!(changes.report().last().unwrap().contains("(tokio)"));
+        assert!(PackageChanges::new(&new, &new).is_empty());
+    }
+}
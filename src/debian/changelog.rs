@@ -3,7 +3,7 @@ use chrono::{DateTime, FixedOffset, Local, TimeZone};
 use regex::Regex;
 
 use std::fmt;
-use std::str;
+use std::str::{self, FromStr};
 
 pub const DEFAULT_DIST: &str = "UNRELEASED-FIXME-AUTOGENERATED-DEBCARGO";
 pub const COMMENT_TEAM_UPLOAD: &str = "  * Team upload.";
@@ -78,8 +78,8 @@ impl str::FromStr for ChangelogEntry {
         Ok(Self::new(
             matches1[1].to_string(),
             matches1[2].to_string(),
-            matches1[3].to_string(),
-            matches1[4].to_string(),
+            matches1[3].trim().to_string(),
+            matches1[4].trim().to_string(),
             matches2[1].to_string(),
             DateTime::parse_from_rfc2822(&matches2[2])?,
             lines.iter().map(|s| s.to_string()).collect(),
@@ -120,6 +120,15 @@ impl ChangelogEntry {
         (matches[1].to_string(), matches[2].to_string())
     }
 
+    /// The upstream part of the version, without any epoch.
+    pub fn upstream_version(self: &ChangelogEntry) -> String {
+        let version = self.version_parts().0;
+        match version.split_once(':') {
+            Some((_, upstream)) => upstream.to_string(),
+            None => version,
+        }
+    }
+
     pub fn deb_version_suffix(self: &ChangelogEntry) -> String {
         let re = Regex::new(r".*-([^-]*)$").unwrap();
         re.captures(&self.version).unwrap()[1].to_string()
@@ -141,6 +150,19 @@ impl ChangelogEntry {
     }
 }
 
+/// Upstream version of the latest entry in a changelog that is not an
+/// unreleased debcargo-generated one, i.e. the version last packaged. Any
+/// epoch is stripped.
+pub fn last_packaged_version(changelog_data: &str) -> anyhow::Result<Option<String>> {
+    for x in ChangelogIterator::from(changelog_data) {
+        if x.contains(DEFAULT_DIST) {
+            continue;
+        }
+        return Ok(Some(ChangelogEntry::from_str(x)?.upstream_version()));
+    }
+    Ok(None)
+}
+
 pub struct ChangelogIterator<'a> {
     input: &'a [u8],
     index: usize,
@@ -181,3 +203,29 @@ impl<'a> Iterator for ChangelogIterator<'a> {
         Some(str::from_utf8(result).unwrap())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::last_packaged_version;
+
+    #[test]
+    fn last_packaged_version_strips_epoch() {
+        let changelog = "\
+rust-foo (1:1.3.0-1) UNRELEASED-FIXME-AUTOGENERATED-DEBCARGO; urgency=medium
+
+  * Package foo 1.3.0 from crates.io using debcargo 2.6.1
+
+ -- Jane Doe <jane@example.com>  Sat, 17 Oct 2026 00:00:00 +0000
+
+rust-foo (1:1.2.3-2) unstable; urgency=medium
+
+  * Package foo 1.2.3 from crates.io using debcargo 2.6.1
+
+ -- Jane Doe <jane@example.com>  Fri, 16 Oct 2026 00:00:00 +0000
+";
+        assert_eq!(
+            last_packaged_version(changelog).unwrap().as_deref(),
+            Some("1.2.3")
+        );
+    }
+}
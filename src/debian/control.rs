@@ -7,12 +7,51 @@ use textwrap::fill;
 
 use crate::config::{self, Config, PackageKey};
 use crate::errors::*;
+use crate::profile::Profile;
+
+/// Fill in the placeholders of a Vcs-* URL template, e.g. "{name}", from
+/// `vars`. A value that uses none of them is taken literally, so that plain
+/// URLs containing braces still work.
+fn render_vcs_template(template: &str, vars: &[(&str, &str)]) -> Result<String> {
+    if !vars
+        .iter()
+        .any(|(k, _)| template.contains(&format!("{{{}}}", k)))
+    {
+        return Ok(template.to_string());
+    }
+    let mut rendered = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => debcargo_bail!("unclosed placeholder in Vcs URL template: {}", template),
+        };
+        let name = &rest[start + 1..end];
+        match vars.iter().find(|(k, _)| *k == name) {
+            Some((_, v)) => rendered.push_str(v),
+            None => debcargo_bail!(
+                "unknown placeholder {{{}}} in Vcs URL template {}; known ones are {}",
+                name,
+                template,
+                vars.iter()
+                    .map(|(k, _)| format!("{{{}}}", k))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+        rest = &rest[end + 1..];
+    }
+    rendered.push_str(rest);
+    Ok(rendered)
+}
 
 pub struct Source {
     name: String,
     section: String,
     priority: String,
     maintainer: String,
+    original_maintainer: Option<String>,
     uploaders: Vec<String>,
     standards: String,
     build_deps: Vec<String>,
@@ -21,6 +60,7 @@ pub struct Source {
     homepage: String,
     crate_name: String,
     requires_root: String,
+    comments: Vec<String>,
 }
 
 pub struct Package {
@@ -50,15 +90,24 @@ pub struct PkgTest {
     extra_test_args: Vec<String>,
     depends: Vec<String>,
     extra_restricts: Vec<String>,
+    dh_cargo: String,
 }
 
 impl fmt::Display for Source {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for comment in &self.comments {
+            for line in fill(comment, 77).lines() {
+                writeln!(f, "# {}", line)?;
+            }
+        }
         writeln!(f, "Source: {}", self.name)?;
         writeln!(f, "Section: {}", self.section)?;
         writeln!(f, "Priority: {}", self.priority)?;
         writeln!(f, "Build-Depends: {}", self.build_deps.join(",\n "))?;
         writeln!(f, "Maintainer: {}", self.maintainer)?;
+        if let Some(ref original) = self.original_maintainer {
+            writeln!(f, "XSBC-Original-Maintainer: {}", original)?;
+        }
         if !self.uploaders.is_empty() {
             writeln!(f, "Uploaders:\n {}", self.uploaders.join(",\n "))?;
         }
@@ -137,7 +186,11 @@ impl fmt::Display for PkgTest {
         } else {
             format!(", {}", self.depends.join(", "))
         };
-        writeln!(f, "Depends: dh-cargo (>= 31){}, {}", depends, default_deps)?;
+        writeln!(
+            f,
+            "Depends: dh-cargo (>= {}){}, {}",
+            self.dh_cargo, depends, default_deps
+        )?;
 
         let restricts = if self.extra_restricts.is_empty() {
             "".into()
@@ -154,26 +207,21 @@ impl fmt::Display for PkgTest {
 }
 
 impl Source {
-    pub fn pkg_prefix() -> &'static str {
-        if config::testing_ruzt() {
-            // avoid accidentally installing official packages during tests
-            "ruzt"
-        } else {
-            "rust"
-        }
-    }
-
     #[allow(clippy::too_many_arguments)]
     pub fn new(
+        profile: &Profile,
         basename: &str,
         name_suffix: Option<&str>,
         crate_name: &str,
+        version: &str,
         home: &str,
         lib: bool,
         maintainer: String,
         uploaders: Vec<String>,
         build_deps: Vec<String>,
         _requires_root: String,
+        vcs_git: &str,
+        vcs_browser: &str,
     ) -> Result<Source> {
         let pkgbase = match name_suffix {
             None => basename.to_string(),
@@ -185,27 +233,35 @@ impl Source {
             "FIXME-IN-THE-SOURCE-SECTION"
         };
         let priority = "optional".to_string();
-        let vcs_browser = format!(
-            "https://salsa.debian.org/rust-team/debcargo-conf/tree/master/src/{}",
-            pkgbase
-        );
-        let vcs_git = format!(
-            "https://salsa.debian.org/rust-team/debcargo-conf.git [src/{}]",
-            pkgbase
-        );
+        let name = dsc_name(profile, &pkgbase);
+        let vars = [
+            ("source", name.as_str()),
+            ("name", pkgbase.as_str()),
+            ("crate", crate_name),
+            ("version", version),
+            ("semver_suffix", name_suffix.unwrap_or("")),
+        ];
+        let vcs_git = render_vcs_template(vcs_git, &vars)?;
+        let vcs_browser = render_vcs_template(vcs_browser, &vars)?;
+        let (maintainer, original_maintainer) = match profile.maintainer_override {
+            Some(ref m) => (m.clone(), Some(maintainer)),
+            None => (maintainer, None),
+        };
         Ok(Source {
-            name: dsc_name(&pkgbase),
+            name,
             section: section.to_string(),
             priority,
             maintainer,
+            original_maintainer,
             uploaders,
-            standards: "4.6.2".to_string(),
+            standards: profile.standards_version.clone(),
             build_deps,
             vcs_git,
             vcs_browser,
             homepage: home.to_string(),
             crate_name: crate_name.to_string(),
             requires_root: "no".to_string(),
+            comments: Vec::new(),
         })
     }
 
@@ -213,6 +269,12 @@ impl Source {
         &self.name
     }
 
+    /// Add an informational comment, to be written before the source package
+    /// paragraph.
+    pub fn add_comment(&mut self, comment: &str) {
+        self.comments.push(comment.to_string());
+    }
+
     pub fn apply_overrides(&mut self, config: &Config) {
         if let Some(section) = config.section() {
             self.section = section.to_string();
@@ -239,14 +301,6 @@ impl Source {
             self.homepage = homepage.to_string();
         }
 
-        if let Some(vcs_git) = config.vcs_git() {
-            self.vcs_git = vcs_git.to_string();
-        }
-
-        if let Some(vcs_browser) = config.vcs_browser() {
-            self.vcs_browser = vcs_browser.to_string();
-        }
-
         if let Some(requires_root) = config.requires_root() {
             self.requires_root = requires_root.to_string();
         }
@@ -254,17 +308,9 @@ impl Source {
 }
 
 impl Package {
-    pub fn pkg_prefix() -> &'static str {
-        if config::testing_ruzt() {
-            // avoid accidentally installing official packages during tests
-            "libruzt"
-        } else {
-            "librust"
-        }
-    }
-
     #[allow(clippy::too_many_arguments)]
     pub fn new(
+        profile: &Profile,
         basename: &str,
         name_suffix: Option<&str>,
         version: &Version,
@@ -285,8 +331,8 @@ impl Package {
             format!(
                 "{} (= ${{binary:Version}})",
                 match f {
-                    "" => deb_name(p),
-                    _ => deb_feature_name(p, f),
+                    "" => deb_name(profile, p),
+                    _ => deb_feature_name(profile, p, f),
                 }
             )
         };
@@ -336,8 +382,8 @@ impl Package {
 
         Ok(Package {
             name: match feature {
-                None => deb_name(&pkgbase),
-                Some(f) => deb_feature_name(&pkgbase, f),
+                None => deb_name(profile, &pkgbase),
+                Some(f) => deb_feature_name(profile, &pkgbase, f),
             },
             arch: "any".to_string(),
             // This is the best but not ideal option for us.
@@ -372,8 +418,8 @@ impl Package {
                 (Some(_), None) => {
                     let fullpkg = format!("{}-{}", basename, version);
                     vec![
-                        format!("Replaces: {}", deb_name(&fullpkg)),
-                        format!("Breaks: {}", deb_name(&fullpkg)),
+                        format!("Replaces: {}", deb_name(profile, &fullpkg)),
+                        format!("Breaks: {}", deb_name(profile, &fullpkg)),
                     ]
                 }
                 (_, _) => vec![],
@@ -504,7 +550,9 @@ impl fmt::Display for Description {
 }
 
 impl PkgTest {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
+        profile: &Profile,
         name: &str,
         crate_name: &str,
         feature: &str,
@@ -521,6 +569,7 @@ impl PkgTest {
             extra_test_args: extra_test_args.iter().map(|x| x.to_string()).collect(),
             depends: depends.to_vec(),
             extra_restricts: extra_restricts.iter().map(|x| x.to_string()).collect(),
+            dh_cargo: profile.dh_cargo_tests.clone(),
         })
     }
 }
@@ -540,18 +589,18 @@ pub fn base_deb_name(crate_name: &str) -> String {
     crate_name.replace('_', "-").to_lowercase()
 }
 
-pub fn dsc_name(name: &str) -> String {
-    format!("{}-{}", Source::pkg_prefix(), base_deb_name(name))
+pub fn dsc_name(profile: &Profile, name: &str) -> String {
+    format!("{}-{}", profile.source_prefix, base_deb_name(name))
 }
 
-pub fn deb_name(name: &str) -> String {
-    format!("{}-{}-dev", Package::pkg_prefix(), base_deb_name(name))
+pub fn deb_name(profile: &Profile, name: &str) -> String {
+    format!("{}-{}-dev", profile.package_prefix, base_deb_name(name))
 }
 
-pub fn deb_feature_name(name: &str, feature: &str) -> String {
+pub fn deb_feature_name(profile: &Profile, name: &str, feature: &str) -> String {
     format!(
         "{}-{}+{}-dev",
-        Package::pkg_prefix(),
+        profile.package_prefix,
         base_deb_name(name),
         base_deb_name(feature)
     )
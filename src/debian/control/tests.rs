@@ -1,4 +1,5 @@
-use super::PkgTest;
+use super::{render_vcs_template, PkgTest};
+use crate::profile::Profile;
 
 struct PkgTestFmtData<'a> {
     feature: &'a str,
@@ -26,6 +27,7 @@ fn pkgtest_fmt_has_no_extra_whitespace() {
 
     for check in checks {
         let pkgtest = PkgTest::new(
+            &Profile::debian(),
             "librust-crate-dev",
             "crate",
             check.feature,
@@ -42,3 +44,32 @@ fn pkgtest_fmt_has_no_extra_whitespace() {
         }
     }
 }
+
+#[test]
+fn vcs_templates() {
+    let vars = [
+        ("source", "rust-clap-2"),
+        ("name", "clap-2"),
+        ("crate", "clap"),
+        ("version", "2.34.0"),
+        ("semver_suffix", "-2"),
+    ];
+    assert_eq!(
+        render_vcs_template(&Profile::debian().vcs_git, &vars).unwrap(),
+        "https://salsa.debian.org/rust-team/debcargo-conf.git [src/clap-2]"
+    );
+    assert_eq!(
+        render_vcs_template(
+            "https://git.example.org/{source}.git -b {crate}{semver_suffix}/{version}",
+            &vars
+        )
+        .unwrap(),
+        "https://git.example.org/rust-clap-2.git -b clap-2/2.34.0"
+    );
+    assert!(render_vcs_template("https://git.example.org/{package}/{name}", &vars).is_err());
+    assert!(render_vcs_template("https://git.example.org/{name}/{crate", &vars).is_err());
+    assert_eq!(
+        render_vcs_template("https://git.example.org/{x}/rust-clap.git", &vars).unwrap(),
+        "https://git.example.org/{x}/rust-clap.git"
+    );
+}
@@ -1,5 +1,6 @@
+use anyhow::Context;
 use cargo::core::manifest;
-use chrono::{Datelike, NaiveDateTime, TimeZone, Utc};
+use chrono::{DateTime, Datelike, NaiveDateTime, TimeZone, Utc};
 use git2::Repository;
 use regex;
 use tempfile;
@@ -12,10 +13,13 @@ use std::env;
 use std::fmt;
 use std::fs;
 use std::io::{BufRead, BufReader, Read};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use crate::errors::*;
 
+mod matcher;
+mod spdx;
+
 const DEB_COPYRIGHT_FORMAT: &str = "\
      https://www.debian.org/doc/packaging-manuals/copyright-format/1.0/";
 
@@ -185,8 +189,11 @@ macro_rules! default_files {
     }};
 }
 
-fn gen_files(debsrcdir: &Path) -> Result<Vec<Files>> {
+/// Files paragraphs for the copyright notices found in each file, along with
+/// the paths of all files, including those without any notice.
+fn gen_files(debsrcdir: &Path) -> Result<(Vec<Files>, Vec<String>)> {
     let mut copyright_notices = BTreeMap::new();
+    let mut all_files = Vec::new();
 
     let copyright_notice_re =
         regex::Regex::new(r"(?:[Cc]opyright|©)(?:\s|[©:,()Cc<])*\b(\d{4}\b.*)$")?;
@@ -210,6 +217,7 @@ fn gen_files(debsrcdir: &Path) -> Result<Vec<Files>> {
                 .to_str()
                 .unwrap()
                 .to_string();
+            all_files.push(copyright_file.clone());
             let file = fs::File::open(entry.path())?;
             let reader = BufReader::new(file);
             for line in reader.lines() {
@@ -246,70 +254,339 @@ fn gen_files(debsrcdir: &Path) -> Result<Vec<Files>> {
         notices.push(default_files!(filename, notice));
     }
 
-    Ok(notices)
+    Ok((notices, all_files))
+}
+
+const UNKNOWN_LICENSE_TEXT: &str = "FIXME (overlay): Unrecognized crate license, please find the \
+     full license text in the rest of the crate source code and copy-paste it here";
+
+fn license_text(id: &str) -> Option<&'static str> {
+    let text = match id {
+        "0BSD" => include_str!("licenses/0BSD"),
+        "AGPL-3.0" => include_str!("licenses/AGPL-3.0"),
+        "Apache-2.0" => include_str!("licenses/Apache-2.0"),
+        "BSD-2-Clause" => include_str!("licenses/BSD-2-Clause"),
+        "BSD-3-Clause" => include_str!("licenses/BSD-3-Clause"),
+        "BSL-1.0" => include_str!("licenses/BSL-1.0"),
+        "CC0-1.0" => include_str!("licenses/CC0-1.0"),
+        "GPL-2.0" => include_str!("licenses/GPL-2.0"),
+        "GPL-3.0" => include_str!("licenses/GPL-3.0"),
+        "ISC" => include_str!("licenses/ISC"),
+        "LGPL-2.0" => include_str!("licenses/LGPL-2.0"),
+        "LGPL-2.1" => include_str!("licenses/LGPL-2.1"),
+        "LGPL-3.0" => include_str!("licenses/LGPL-3.0"),
+        "MIT" => include_str!("licenses/MIT"),
+        "MITNFA" => include_str!("licenses/MITNFA"),
+        "MPL-1.1" => include_str!("licenses/MPL-1.1"),
+        "MPL-2.0" => include_str!("licenses/MPL-2.0"),
+        "Unicode-DFS-2016" => include_str!("licenses/Unicode-DFS-2016"),
+        "Unlicense" => include_str!("licenses/Unlicense"),
+        "Zlib" => include_str!("licenses/Zlib"),
+        _ => return None,
+    };
+    Some(text)
+}
+
+fn exception_text(id: &str) -> Option<&'static str> {
+    match id {
+        "LLVM-exception" => Some(include_str!("licenses/LLVM-exception")),
+        _ => None,
+    }
 }
 
-fn get_licenses(license: &str) -> Result<Vec<License>> {
+/// Translate the SPDX license expression of a crate into a DEP-5 License
+/// expression, and a license paragraph for each license in it.
+fn get_licenses(license: &str) -> Result<(String, Vec<License>)> {
+    let expr = spdx::parse(license)?;
     let mut licenses = BTreeMap::new();
-    let sep = regex::Regex::new(r"(?i:(\s(or|and)\s|/))")?;
-    let known_licenses = vec![
-        ("agpl-3.0", include_str!("licenses/AGPL-3.0")),
-        ("apache-2.0", include_str!("licenses/Apache-2.0")),
-        ("bsd-2-clause", include_str!("licenses/BSD-2-Clause")),
-        ("bsd-3-clause", include_str!("licenses/BSD-3-Clause")),
-        ("cc0-1.0", include_str!("licenses/CC0-1.0")),
-        ("gpl-2.0", include_str!("licenses/GPL-2.0")),
-        ("gpl-3.0", include_str!("licenses/GPL-3.0")),
-        ("isc", include_str!("licenses/ISC")),
-        ("lgpl-2.0", include_str!("licenses/LGPL-2.0")),
-        ("lgpl-2.1", include_str!("licenses/LGPL-2.1")),
-        ("lgpl-3.0", include_str!("licenses/LGPL-3.0")),
-        ("mit", include_str!("licenses/MIT")),
-        ("mitnfa", include_str!("licenses/MITNFA")),
-        ("mpl-1.1", include_str!("licenses/MPL-1.1")),
-        ("mpl-2.0", include_str!("licenses/MPL-2.0")),
-        ("unlicense", include_str!("licenses/Unlicense")),
-        ("zlib", include_str!("licenses/Zlib")),
-    ]
-    .into_iter()
-    .collect::<BTreeMap<_, _>>();
-
-    let lses: Vec<&str> = sep.split(license).filter(|s| !s.is_empty()).collect();
-    for ls in lses {
-        let lname = ls
-            .trim()
-            .to_lowercase()
-            .trim_end_matches('+')
-            .trim_end_matches("-or-later")
+    for leaf in expr.leaves() {
+        let mut text = license_text(&leaf.id)
+            .unwrap_or(UNKNOWN_LICENSE_TEXT)
             .to_string();
-        let text = match known_licenses.get(lname.as_str()) {
-            Some(s) => s.to_string(),
-            None => "FIXME (overlay): Unrecognized crate license, please find the \
-                     full license text in the rest of the crate source code and \
-                     copy-paste it here"
-                .to_string(),
+        if let Some(ref exception) = leaf.exception {
+            text.push_str("\n\n");
+            match exception_text(exception) {
+                Some(t) => text.push_str(t),
+                None => text.push_str(&format!(
+                    "FIXME (overlay): Unrecognized license exception {}, please \
+                     copy-paste its text here",
+                    exception
+                )),
+            }
+        }
+        licenses.insert(leaf.dep5_name(), text);
+    }
+
+    let lblocks = licenses
+        .into_iter()
+        .map(|(l, t)| License::new(l, t))
+        .collect();
+    Ok((expr.to_dep5(), lblocks))
+}
+
+#[derive(Default)]
+struct Dir {
+    /// The key of each file's paragraph, None for files without notices.
+    files: BTreeMap<String, Option<usize>>,
+    dirs: BTreeMap<String, Dir>,
+}
+
+impl Dir {
+    /// The key shared by all files in this directory and its subdirectories.
+    fn uniform(&self) -> Option<usize> {
+        let mut keys = self
+            .files
+            .values()
+            .copied()
+            .chain(self.dirs.values().map(Dir::uniform));
+        let first = keys.next()??;
+        if keys.all(|k| k == Some(first)) {
+            Some(first)
+        } else {
+            None
+        }
+    }
+
+    fn patterns(&self, prefix: &str, out: &mut Vec<(String, usize)>) {
+        for (name, dir) in &self.dirs {
+            let path = format!("{}{}", prefix, name);
+            match dir.uniform() {
+                Some(key) => out.push((format!("{}/*", path), key)),
+                None => dir.patterns(&format!("{}/", path), out),
+            }
+        }
+        for (name, &key) in &self.files {
+            if let Some(key) = key {
+                out.push((format!("{}{}", prefix, name), key));
+            }
+        }
+    }
+}
+
+/// Merge the per-file paragraphs from `gen_files` that have the same notices,
+/// license and comment. A directory whose files all have the same ones gets a
+/// single `dir/*` pattern; other files are listed together in one paragraph.
+/// `all_files` are the paths of all files, so that a directory with files
+/// that have no notice is never claimed as a whole.
+fn group_files(files: Vec<Files>, all_files: &[String]) -> Vec<Files> {
+    let mut keys: Vec<Files> = Vec::new();
+    let mut root = Dir::default();
+    let mut insert = |path: &str, key: Option<usize>| {
+        let mut parts = path.split('/').collect::<Vec<_>>();
+        let name = parts.pop().unwrap();
+        let mut dir = &mut root;
+        for part in parts {
+            dir = dir.dirs.entry(part.to_string()).or_default();
+        }
+        dir.files.insert(name.to_string(), key);
+    };
+    for path in all_files {
+        insert(path, None);
+    }
+    for file in files {
+        let key = match keys.iter().position(|k| {
+            k.copyright == file.copyright && k.license == file.license && k.comment == file.comment
+        }) {
+            Some(key) => key,
+            None => {
+                keys.push(file.clone());
+                keys.len() - 1
+            }
+        };
+        insert(&file.files, Some(key));
+    }
+
+    let mut patterns = Vec::new();
+    root.patterns("", &mut patterns);
+    let mut by_key: BTreeMap<usize, Vec<String>> = BTreeMap::new();
+    for (pattern, key) in patterns {
+        by_key.entry(key).or_default().push(pattern);
+    }
+    by_key
+        .into_iter()
+        .map(|(key, patterns)| Files {
+            files: patterns.join("\n "),
+            ..keys[key].clone()
+        })
+        .collect()
+}
+
+/// Whether a paragraph says nothing beyond the catch-all one: it has the
+/// crate's license, or an unknown one, and its notices are by crate authors.
+fn is_redundant(file: &Files, crate_license: &str, authors: &[String]) -> bool {
+    if file.license != crate_license && file.license != "UNKNOWN-LICENSE; FIXME (overlay)" {
+        return false;
+    }
+    let names = authors
+        .iter()
+        .map(|a| a.split(" <").next().unwrap().trim())
+        .filter(|a| !a.is_empty())
+        .collect::<Vec<_>>();
+    file.copyright
+        .iter()
+        .all(|c| names.iter().any(|n| c.contains(n)))
+}
+
+fn is_license_file(name: &str) -> bool {
+    let name = name.to_uppercase();
+    ["LICENSE", "LICENCE", "COPYING", "UNLICENSE"]
+        .iter()
+        .any(|p| name.starts_with(p))
+}
+
+/// Files paragraphs for subdirectories, typically vendored code, that carry
+/// license files for licenses other than the crate's, together with license
+/// paragraphs for any licenses not already in `licenses`.
+fn subdir_licenses(
+    srcdir: &Path,
+    licenses: &[License],
+    notices: &[Files],
+) -> Result<(Vec<Files>, Vec<License>)> {
+    let mut by_dir: BTreeMap<PathBuf, Vec<(PathBuf, Option<&str>)>> = BTreeMap::new();
+    for entry in walkdir::WalkDir::new(srcdir)
+        .min_depth(2)
+        .sort_by(|a, b| a.file_name().cmp(b.file_name()))
+    {
+        let entry = entry?;
+        if !entry.file_type().is_file() || !is_license_file(&entry.file_name().to_string_lossy()) {
+            continue;
+        }
+        let text = match fs::read_to_string(entry.path()) {
+            Ok(text) => text,
+            Err(_) => continue,
         };
-        licenses.insert(ls.trim().to_string(), text);
+        let path = entry.path().strip_prefix(srcdir)?.to_path_buf();
+        by_dir
+            .entry(path.parent().unwrap().to_path_buf())
+            .or_default()
+            .push((path, matcher::identify(&text)));
     }
 
-    let mut lblocks: Vec<License> = Vec::new();
-    if !licenses.is_empty() {
-        for (l, t) in licenses {
-            lblocks.push(License::new(l, t));
+    let mut files = Vec::new();
+    let mut new_licenses = BTreeMap::new();
+    for (dir, found) in by_dir {
+        let mut names = Vec::new();
+        let mut new = BTreeMap::new();
+        for (_, expr) in &found {
+            match expr {
+                Some(expr) => {
+                    let (name, lblocks) = get_licenses(expr)?;
+                    for l in lblocks {
+                        if !licenses.iter().any(|c| c.name == l.name) {
+                            new.insert(l.name, l.text);
+                        }
+                    }
+                    names.push(name);
+                }
+                None => names.push("UNKNOWN-LICENSE; FIXME (overlay)".to_string()),
+            }
+        }
+        // the same licenses as the crate, which the catch-all paragraph covers
+        if new.is_empty() && found.iter().all(|(_, e)| e.is_some()) {
+            continue;
+        }
+        new_licenses.extend(new);
+
+        let mut copyright: Vec<&str> = Vec::new();
+        for f in notices
+            .iter()
+            .filter(|f| Path::new(&f.files).starts_with(&dir))
+        {
+            for c in &f.copyright {
+                if !copyright.contains(&c.as_str()) {
+                    copyright.push(c);
+                }
+            }
+        }
+        if copyright.is_empty() {
+            copyright.push("FIXME (overlay) UNKNOWN-AUTHORS");
+        }
+        let mut comment = format!(
+            "FIXME (overlay): The license was guessed from {}. Please review \
+             it before uploading to the archive.",
+            found
+                .iter()
+                .map(|(p, _)| p.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        if names.len() > 1 {
+            comment.push_str(
+                " There are several license files; check whether they apply \
+                 together or as alternatives.",
+            );
         }
+        files.push(Files::new(
+            &format!("{}/*", dir.display()),
+            &copyright,
+            &names.join(" and "),
+            &fill(&comment, 79),
+        ));
     }
 
-    Ok(lblocks)
+    let new_licenses = new_licenses
+        .into_iter()
+        .map(|(l, t)| License::new(l, t))
+        .collect();
+    Ok((files, new_licenses))
 }
 
-fn copyright_fromgit(repo_url: &str) -> Result<String> {
-    let tempdir = tempfile::Builder::new()
-        .prefix("debcargo")
-        .tempdir_in(".")?;
-    let repo = Repository::clone(repo_url, tempdir.path())?;
+/// Where to look for upstream copyright years, for --copyright-guess-harder.
+pub struct YearSources {
+    /// Don't clone the upstream repository over the network.
+    pub offline: bool,
+    /// A local clone of the upstream repository.
+    pub upstream_clone: Option<PathBuf>,
+    /// The entries for the releases of the crate in the local registry index.
+    pub releases: Vec<serde_json::Value>,
+}
 
+fn year_of(seconds: i64) -> Result<i32> {
+    Ok(Utc
+        .from_utc_datetime(
+            &NaiveDateTime::from_timestamp_opt(seconds, 0).ok_or(anyhow::Error::msg("lo"))?,
+        )
+        .year())
+}
+
+fn years_notice(first_year: i32, latest_year: i32) -> String {
+    match first_year.cmp(&latest_year) {
+        Ordering::Equal => format!("{}", first_year),
+        _ => format!("{}-{},", first_year, latest_year),
+    }
+}
+
+/// The .cargo_vcs_info.json that cargo writes into the crates it publishes
+/// from a repository, if any.
+pub(crate) fn cargo_vcs_info(srcdir: &Path) -> Option<serde_json::Value> {
+    let info = fs::read_to_string(srcdir.join(".cargo_vcs_info.json")).ok()?;
+    serde_json::from_str(&info).ok()
+}
+
+/// The commit that the crate was published from, as recorded by cargo.
+fn vcs_sha1(srcdir: &Path) -> Option<String> {
+    let info = cargo_vcs_info(srcdir)?;
+    Some(info.get("git")?.get("sha1")?.as_str()?.to_string())
+}
+
+/// The repository URL without the path of a subdirectory on the forge's
+/// website, as in https://github.com/BurntSushi/ripgrep/tree/master/crates/globset
+pub(crate) fn repository_clone_url(url: &str) -> &str {
+    ["/-/tree/", "/tree/", "/blob/", "/src/branch/"]
+        .iter()
+        .find_map(|p| url.find(p))
+        .map_or(url, |i| &url[..i])
+        .trim_end_matches('/')
+}
+
+fn copyright_fromrepo(repo: &Repository, sha1: Option<&str>) -> Result<String> {
     let mut revwalker = repo.revwalk()?;
-    revwalker.push_head()?;
+    match sha1 {
+        Some(sha1) => revwalker
+            .push(git2::Oid::from_str(sha1)?)
+            .with_context(|| format!("commit {} is not in the repository", sha1))?,
+        None => revwalker.push_head()?,
+    }
 
     // Get the latest and first commit id. This is bit ugly
     let latest_id = revwalker.next().unwrap()?;
@@ -318,28 +595,93 @@ fn copyright_fromgit(repo_url: &str) -> Result<String> {
     let first_commit = repo.find_commit(first_id)?;
     let latest_commit = repo.find_commit(latest_id)?;
 
-    let first_year = Utc
-        .from_utc_datetime(
-            &NaiveDateTime::from_timestamp_opt(first_commit.time().seconds(), 0)
-                .ok_or(anyhow::Error::msg("lo"))?,
-        )
-        .year();
+    Ok(years_notice(
+        year_of(first_commit.time().seconds())?,
+        year_of(latest_commit.time().seconds())?,
+    ))
+}
 
-    let latest_year = Utc
-        .from_utc_datetime(
-            &NaiveDateTime::from_timestamp_opt(latest_commit.time().seconds(), 0)
-                .ok_or(anyhow::Error::msg("lo"))?,
-        )
-        .year();
+fn copyright_fromgit(repo_url: &str, sha1: Option<&str>) -> Result<String> {
+    let tempdir = tempfile::Builder::new()
+        .prefix("debcargo")
+        .tempdir_in(".")?;
+    let repo = Repository::clone(repo_url, tempdir.path())?;
+    copyright_fromrepo(&repo, sha1)
+}
 
-    let notice = match first_year.cmp(&latest_year) {
-        Ordering::Equal => format!("{}", first_year),
-        _ => format!("{}-{},", first_year, latest_year),
+/// The years of the earliest and latest releases, going by the publication
+/// times in their index entries, and the number of releases that have one.
+/// The mtimes in .crate files are no use for this, since cargo has been
+/// setting them to a fixed date for reproducibility.
+fn copyright_fromreleases(releases: &[serde_json::Value]) -> Option<(String, usize)> {
+    let years = releases
+        .iter()
+        .filter_map(|r| r.get("pubtime")?.as_str())
+        .filter_map(|t| DateTime::parse_from_rfc3339(t).ok())
+        .map(|t| t.year())
+        .collect::<Vec<_>>();
+    let first = *years.iter().min()?;
+    let latest = *years.iter().max()?;
+    Some((years_notice(first, latest), years.len()))
+}
+
+/// Upstream copyright years, with a description of where they came from.
+fn guess_years(srcdir: &Path, repository: &str, sources: &YearSources) -> Option<(String, String)> {
+    let sha1 = vcs_sha1(srcdir);
+    let upto = match sha1 {
+        Some(ref sha1) => format!(", up to commit {} from .cargo_vcs_info.json", sha1),
+        None => "".to_string(),
     };
 
-    Ok(notice)
+    if let Some(ref clone) = sources.upstream_clone {
+        match Repository::open(clone)
+            .map_err(anyhow::Error::from)
+            .and_then(|repo| copyright_fromrepo(&repo, sha1.as_deref()))
+        {
+            Ok(years) => {
+                let provenance = format!(
+                    "they were extracted from the Git history of the local clone {}{}",
+                    clone.display(),
+                    upto
+                );
+                return Some((years, provenance));
+            }
+            Err(e) => debcargo_warn!(
+                "Failed to get copyright years from local clone {}: {}",
+                clone.display(),
+                e
+            ),
+        }
+    }
+
+    if !sources.offline && !repository.is_empty() {
+        let url = repository_clone_url(repository);
+        match copyright_fromgit(url, sha1.as_deref()) {
+            Ok(years) => {
+                let provenance = format!(
+                    "they were extracted from the upstream Git repository {}{}",
+                    url, upto
+                );
+                return Some((years, provenance));
+            }
+            Err(e) => debcargo_warn!(
+                "Failed to generate d/copyright from git repository {}: {}\n",
+                url,
+                e
+            ),
+        }
+    }
+
+    let (years, n) = copyright_fromreleases(&sources.releases)?;
+    let provenance = format!(
+        "they span the publication dates of the {} releases of the crate in \
+         the local registry index, so the code may be older than that",
+        n
+    );
+    Some((years, provenance))
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn debian_copyright(
     srcdir: &Path,
     manifest: &manifest::Manifest,
@@ -347,7 +689,8 @@ pub fn debian_copyright(
     maintainer: &str,
     uploaders: &[&str],
     year_range: (i32, i32),
-    guess_harder: bool,
+    guess_harder: Option<&YearSources>,
+    per_file: bool,
 ) -> Result<DebCopyright> {
     let meta = manifest.metadata().clone();
     let repository = match meta.repository {
@@ -380,22 +723,60 @@ pub fn debian_copyright(
                 format!("Failed to read license from {license_file_name} - {e:?}")
             }
         };
-        licenses.push(License::new(
-            "UNKNOWN-LICENSE; FIXME (overlay)".to_string(),
-            stext,
-        ));
+        match matcher::identify(&stext) {
+            Some(expr) => {
+                let (expr, lblocks) = get_licenses(expr)?;
+                crate_license = expr;
+                licenses = lblocks;
+            }
+            None => licenses.push(License::new(
+                "UNKNOWN-LICENSE; FIXME (overlay)".to_string(),
+                stext,
+            )),
+        }
     } else if let Some(ref license) = meta.license {
-        licenses = get_licenses(license).unwrap();
-        crate_license = license
-            .trim()
-            .replace('/', " or ")
-            .replace(" OR ", " or ")
-            .replace(" AND ", " and ");
+        match get_licenses(license) {
+            Ok((expr, lblocks)) => {
+                crate_license = expr;
+                licenses = lblocks;
+            }
+            Err(e) => {
+                debcargo_warn!(
+                    "Crate license {:?} is not a valid SPDX expression: {}",
+                    license,
+                    e
+                );
+                crate_license = "UNKNOWN-LICENSE; FIXME (overlay)".to_string();
+                licenses.push(License::new(
+                    crate_license.clone(),
+                    format!("{} (crate license: {})", UNKNOWN_LICENSE_TEXT, license),
+                ));
+            }
+        }
     } else {
         debcargo_bail!("Crate has no license or license_file");
     }
 
-    let mut files = gen_files(srcdir)?;
+    let (mut files, all_files) = gen_files(srcdir)?;
+    let (subdir_files, subdir_licenses) = subdir_licenses(srcdir, &licenses, &files)?;
+    for file in files.iter_mut() {
+        if let Some(dir) = subdir_files
+            .iter()
+            .rev()
+            .find(|d| Path::new(&file.files).starts_with(d.files.trim_end_matches("/*")))
+        {
+            file.license = dir.license.clone();
+        }
+    }
+    if !per_file {
+        files = group_files(files, &all_files);
+        files.retain(|f| {
+            !subdir_files.iter().any(|d| d.files == f.files)
+                && !is_redundant(f, &crate_license, &meta.authors)
+        });
+    }
+    files.splice(0..0, subdir_files);
+    licenses.extend(subdir_licenses);
 
     let (y0, y1) = year_range;
     let years = if y0 == y1 {
@@ -408,21 +789,25 @@ pub fn debian_copyright(
     files.push(Files::new("debian/*", &deb_notice, &crate_license, ""));
 
     // Insert catch all block as the first block of copyright file. Capture
-    // copyright notice from git log of the upstream repository.
-    let years = if guess_harder && !repository.is_empty() {
-        match copyright_fromgit(repository) {
-            Ok(x) => x,
-            Err(e) => {
-                debcargo_warn!(
-                    "Failed to generate d/copyright from git repository {}: {}\n",
-                    repository,
-                    e
-                );
-                "FIXME (overlay) UNKNOWN-YEARS".to_string()
-            }
-        }
-    } else {
-        "FIXME (overlay) UNKNOWN-YEARS".to_string()
+    // copyright years from the upstream repository or crate releases.
+    let guessed = guess_harder.and_then(|sources| guess_years(srcdir, repository, sources));
+    let (years, comment) = match guessed {
+        Some((years, provenance)) => (
+            years,
+            format!(
+                "FIXME (overlay): Since upstream copyright years are not available \
+                 in Cargo.toml, {}. This may not be correct information so you \
+                 should review and fix this before uploading to the archive.",
+                provenance
+            ),
+        ),
+        None => (
+            "FIXME (overlay) UNKNOWN-YEARS".to_string(),
+            "FIXME (overlay): Upstream copyright years are not available in \
+             Cargo.toml. Please find them, e.g. with --copyright-guess-harder, \
+             before uploading to the archive."
+                .to_string(),
+        ),
     };
     let notice = match meta.authors.len() {
         0 => vec![format!("FIXME (overlay) UNKNOWN-AUTHORS {}", years)],
@@ -433,15 +818,9 @@ pub fn debian_copyright(
             .map(|s| format!("{} {}", years, s))
             .collect(),
     };
-    let comment = concat!(
-        "FIXME (overlay): Since upstream copyright years are not available ",
-        "in Cargo.toml, they were extracted from the upstream Git ",
-        "repository. This may not be correct information so you should ",
-        "review and fix this before uploading to the archive."
-    );
     files.insert(
         0,
-        Files::new("*", notice.as_slice(), &crate_license, &fill(comment, 79)),
+        Files::new("*", notice.as_slice(), &crate_license, &fill(&comment, 79)),
     );
 
     Ok(DebCopyright::new(upstream, &files, &licenses))
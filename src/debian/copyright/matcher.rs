@@ -0,0 +1,165 @@
+//! Identification of licenses from the text of LICENSE and COPYING files, by
+//! comparing them against the license texts bundled with debcargo.
+
+use std::collections::HashSet;
+
+use super::license_text;
+
+/// Licenses whose full text we bundle, identified by comparing whole texts.
+const FULL_TEXT: &[&str] = &[
+    "0BSD",
+    "BSD-2-Clause",
+    "BSD-3-Clause",
+    "BSL-1.0",
+    "ISC",
+    "MIT",
+    "MITNFA",
+    "Unicode-DFS-2016",
+    "Unlicense",
+    "Zlib",
+];
+
+/// Licenses that are too long to compare, identified by a phrase from their
+/// title, in normalized form.
+const FINGERPRINTS: &[(&str, &str)] = &[
+    ("AGPL-3.0", "gnu affero general public license version 3"),
+    ("Apache-2.0", "apache license version 2 0"),
+    ("CC0-1.0", "cc0 1 0 universal"),
+    ("GPL-2.0", "gnu general public license version 2"),
+    ("GPL-3.0", "gnu general public license version 3"),
+    ("LGPL-2.0", "gnu library general public license version 2"),
+    ("LGPL-2.1", "gnu lesser general public license version 2 1"),
+    ("LGPL-3.0", "gnu lesser general public license version 3"),
+    ("MPL-1.1", "mozilla public license version 1 1"),
+    ("MPL-2.0", "mozilla public license version 2 0"),
+];
+
+/// Exceptions, identified by a phrase anywhere in the text, in normalized form.
+const EXCEPTIONS: &[(&str, &str, &str)] = &[(
+    "Apache-2.0",
+    "Apache-2.0 WITH LLVM-exception",
+    "llvm exceptions to the apache 2 0 license",
+)];
+
+/// How many words from the start of a file to search for a fingerprint.
+const FINGERPRINT_WINDOW: usize = 50;
+/// The similarity above which a text is taken to be a bundled license.
+const THRESHOLD: f64 = 0.85;
+
+fn is_notice(line: &str) -> bool {
+    match line.trim_start().to_lowercase().strip_prefix("copyright") {
+        Some(rest) => rest
+            .trim_start()
+            .starts_with(|c: char| c == '(' || c == '©' || c.is_ascii_digit()),
+        None => false,
+    }
+}
+
+/// Lowercase words, ignoring punctuation, layout and copyright notices, which
+/// differ between copies of the same license.
+fn normalize(text: &str) -> Vec<String> {
+    text.lines()
+        .filter(|l| !is_notice(l))
+        .flat_map(|l| {
+            l.to_lowercase()
+                .chars()
+                .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+                .collect::<String>()
+                .split_whitespace()
+                .map(String::from)
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+fn bigrams(words: &[String]) -> HashSet<(&str, &str)> {
+    words
+        .windows(2)
+        .map(|w| (w[0].as_str(), w[1].as_str()))
+        .collect()
+}
+
+/// Dice coefficient of the word bigrams of two normalized texts.
+fn similarity(a: &[String], b: &[String]) -> f64 {
+    let (a, b) = (bigrams(a), bigrams(b));
+    if a.is_empty() && b.is_empty() {
+        return 0.0;
+    }
+    2.0 * a.intersection(&b).count() as f64 / (a.len() + b.len()) as f64
+}
+
+/// The SPDX expression for the license in `text`, if it is one we know.
+pub fn identify(text: &str) -> Option<&'static str> {
+    let words = normalize(text);
+    let head = format!(
+        " {} ",
+        words
+            .iter()
+            .take(FINGERPRINT_WINDOW)
+            .cloned()
+            .collect::<Vec<_>>()
+            .join(" ")
+    );
+    if let Some(&(id, _)) = FINGERPRINTS
+        .iter()
+        .find(|(_, fp)| head.contains(&format!(" {} ", fp)))
+    {
+        let all = format!(" {} ", words.join(" "));
+        return EXCEPTIONS
+            .iter()
+            .find(|(base, _, fp)| *base == id && all.contains(&format!(" {} ", fp)))
+            .map_or(Some(id), |&(_, expr, _)| Some(expr));
+    }
+    FULL_TEXT
+        .iter()
+        .map(|&id| {
+            (
+                id,
+                similarity(&words, &normalize(license_text(id).unwrap())),
+            )
+        })
+        .filter(|&(_, score)| score >= THRESHOLD)
+        .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+        .map(|(id, _)| id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::identify;
+    use crate::debian::copyright::license_text;
+
+    #[test]
+    fn identify_license_files() {
+        let mit = format!(
+            "MIT License\n\nCopyright (c) 2016 Jordan Doe\n\n{}",
+            license_text("MIT").unwrap().replace('\n', " ")
+        );
+        assert_eq!(identify(&mit), Some("MIT"));
+        assert_eq!(
+            identify(license_text("BSD-3-Clause").unwrap()),
+            Some("BSD-3-Clause")
+        );
+        assert_eq!(
+            identify(license_text("BSD-2-Clause").unwrap()),
+            Some("BSD-2-Clause")
+        );
+        assert_eq!(
+            identify(
+                "                    Apache License\n              Version 2.0, January 2004\n"
+            ),
+            Some("Apache-2.0")
+        );
+        assert_eq!(
+            identify(&format!(
+                "Apache License\nVersion 2.0, January 2004\n\n{}",
+                include_str!("../licenses/LLVM-exception")
+            )),
+            Some("Apache-2.0 WITH LLVM-exception")
+        );
+        assert_eq!(
+            identify("GNU LESSER GENERAL PUBLIC LICENSE\nVersion 2.1, February 1999\n"),
+            Some("LGPL-2.1")
+        );
+        assert_eq!(identify("All rights reserved. Do not copy."), None);
+    }
+}
@@ -0,0 +1,392 @@
+//! Parsing of the SPDX license expressions found in the `license` field of
+//! Cargo.toml, and their translation into DEP-5 license names.
+
+use crate::errors::*;
+
+/// Deprecated SPDX ids, as (id, base id, or-later, exception). The GNU ones
+/// without -only or -or-later are handled by `Leaf::new`.
+const DEPRECATED: &[(&str, &str, bool, Option<&str>)] = &[
+    ("BSD-2-Clause-FreeBSD", "BSD-2-Clause", false, None),
+    ("BSD-2-Clause-NetBSD", "BSD-2-Clause", false, None),
+    (
+        "GPL-2.0-with-classpath-exception",
+        "GPL-2.0",
+        false,
+        Some("Classpath-exception-2.0"),
+    ),
+    (
+        "GPL-2.0-with-GCC-exception",
+        "GPL-2.0",
+        false,
+        Some("GCC-exception-2.0"),
+    ),
+    (
+        "GPL-3.0-with-GCC-exception",
+        "GPL-3.0",
+        false,
+        Some("GCC-exception-3.1"),
+    ),
+    ("StandardML-NJ", "SMLNJ", false, None),
+];
+
+/// Ids whose canonical spelling we know, so that e.g. "apache-2.0" or
+/// "mit" are recognised.
+const KNOWN: &[&str] = &[
+    "0BSD",
+    "AGPL-1.0",
+    "AGPL-3.0",
+    "Apache-2.0",
+    "BSD-2-Clause",
+    "BSD-3-Clause",
+    "BSL-1.0",
+    "CC0-1.0",
+    "GFDL-1.2",
+    "GFDL-1.3",
+    "GPL-1.0",
+    "GPL-2.0",
+    "GPL-3.0",
+    "ISC",
+    "LGPL-2.0",
+    "LGPL-2.1",
+    "LGPL-3.0",
+    "MIT",
+    "MIT-0",
+    "MITNFA",
+    "MPL-1.1",
+    "MPL-2.0",
+    "Unicode-3.0",
+    "Unicode-DFS-2016",
+    "Unlicense",
+    "WTFPL",
+    "Zlib",
+];
+
+const EXCEPTIONS: &[&str] = &[
+    "Classpath-exception-2.0",
+    "GCC-exception-2.0",
+    "GCC-exception-3.1",
+    "LLVM-exception",
+    "OpenSSL-exception",
+];
+
+/// GNU licenses, which have -only and -or-later variants in SPDX.
+const GNU: &[&str] = &["AGPL-", "GFDL-", "GPL-", "LGPL-"];
+
+/// A single license, possibly with an exception.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Leaf {
+    /// The SPDX id, without any "-only", "-or-later" or "+" suffix.
+    pub id: String,
+    pub or_later: bool,
+    pub exception: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    License(Leaf),
+    And(Vec<Expr>),
+    Or(Vec<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Open,
+    Close,
+    And,
+    Or,
+    With,
+    Id(String),
+}
+
+fn canonical(id: &str, table: &[&str]) -> String {
+    table
+        .iter()
+        .find(|k| k.eq_ignore_ascii_case(id))
+        .map_or_else(|| id.to_string(), |k| k.to_string())
+}
+
+fn is_gnu(id: &str) -> bool {
+    GNU.iter().any(|p| id.starts_with(p))
+}
+
+impl Leaf {
+    fn new(id: &str) -> Leaf {
+        if let Some(&(_, base, or_later, exception)) = DEPRECATED
+            .iter()
+            .find(|(d, _, _, _)| d.eq_ignore_ascii_case(id))
+        {
+            return Leaf {
+                id: base.to_string(),
+                or_later,
+                exception: exception.map(String::from),
+            };
+        }
+        let (base, or_later) = if let Some(base) = id.strip_suffix('+') {
+            (base, true)
+        } else if let Some(base) = id.strip_suffix("-or-later") {
+            (base, true)
+        } else if let Some(base) = id.strip_suffix("-only") {
+            (base, false)
+        } else {
+            (id, false)
+        };
+        Leaf {
+            id: canonical(base, KNOWN),
+            or_later,
+            exception: None,
+        }
+    }
+
+    /// The DEP-5 short name, e.g. "GPL-2+" for GPL-2.0-or-later. MIT is kept
+    /// as MIT rather than Expat, as used throughout the Rust team's packages.
+    pub fn dep5_name(&self) -> String {
+        let mut name = if is_gnu(&self.id) {
+            self.id.trim_end_matches(".0").to_string()
+        } else if let Some(n) = self
+            .id
+            .strip_prefix("BSD-")
+            .and_then(|s| s.strip_suffix("-Clause"))
+        {
+            format!("BSD-{}-clause", n)
+        } else {
+            self.id.clone()
+        };
+        if self.or_later {
+            name.push('+');
+        }
+        if let Some(ref exception) = self.exception {
+            let short = exception.split("-exception").next().unwrap();
+            name.push_str(&format!(" with {} exception", short));
+        }
+        name
+    }
+}
+
+fn tokenize(expr: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = expr.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            _ if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::Open);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::Close);
+            }
+            // old crates use "MIT/Apache-2.0"
+            '/' => {
+                chars.next();
+                tokens.push(Token::Or);
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || "()/".contains(c) {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                let token = match word.to_ascii_lowercase().as_str() {
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "with" => Token::With,
+                    _ => {
+                        if !word
+                            .chars()
+                            .all(|c| c.is_ascii_alphanumeric() || ".-+:".contains(c))
+                        {
+                            debcargo_bail!("invalid license id {:?}", word);
+                        }
+                        Token::Id(word)
+                    }
+                };
+                tokens.push(token);
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn or_expr(&mut self) -> Result<Expr> {
+        let mut exprs = vec![self.and_expr()?];
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            exprs.push(self.and_expr()?);
+        }
+        Ok(flatten(exprs, true))
+    }
+
+    fn and_expr(&mut self) -> Result<Expr> {
+        let mut exprs = vec![self.with_expr()?];
+        while self.peek() == Some(&Token::And) {
+            self.next();
+            exprs.push(self.with_expr()?);
+        }
+        Ok(flatten(exprs, false))
+    }
+
+    fn with_expr(&mut self) -> Result<Expr> {
+        match self.next() {
+            Some(Token::Open) => {
+                let expr = self.or_expr()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(expr),
+                    _ => debcargo_bail!("missing closing parenthesis"),
+                }
+            }
+            Some(Token::Id(id)) => {
+                let mut leaf = Leaf::new(&id);
+                if self.peek() == Some(&Token::With) {
+                    self.next();
+                    match self.next() {
+                        Some(Token::Id(exception)) => {
+                            leaf.exception = Some(canonical(&exception, EXCEPTIONS))
+                        }
+                        _ => debcargo_bail!("expected an exception after WITH"),
+                    }
+                }
+                Ok(Expr::License(leaf))
+            }
+            Some(t) => debcargo_bail!("expected a license, found {:?}", t),
+            None => debcargo_bail!("expected a license, found the end"),
+        }
+    }
+}
+
+/// Merge nested operators of the same kind, e.g. "A OR (B OR C)".
+fn flatten(exprs: Vec<Expr>, or: bool) -> Expr {
+    if exprs.len() == 1 {
+        return exprs.into_iter().next().unwrap();
+    }
+    let mut flat = Vec::new();
+    for expr in exprs {
+        match expr {
+            Expr::Or(inner) if or => flat.extend(inner),
+            Expr::And(inner) if !or => flat.extend(inner),
+            expr => flat.push(expr),
+        }
+    }
+    if or {
+        Expr::Or(flat)
+    } else {
+        Expr::And(flat)
+    }
+}
+
+/// Parse an SPDX license expression. Operators are accepted in any case,
+/// and "/" is accepted as OR, as older crates use it.
+pub fn parse(expr: &str) -> Result<Expr> {
+    let mut parser = Parser {
+        tokens: tokenize(expr)?,
+        pos: 0,
+    };
+    let parsed = parser.or_expr()?;
+    if let Some(t) = parser.peek() {
+        debcargo_bail!("unexpected {:?} in license expression", t);
+    }
+    Ok(parsed)
+}
+
+impl Expr {
+    /// The DEP-5 License expression. DEP-5 has no parentheses; "and" binds
+    /// more tightly than "or", and ", and" binds less tightly, which covers
+    /// all but the most deeply nested expressions.
+    pub fn to_dep5(&self) -> String {
+        match self {
+            Expr::License(leaf) => leaf.dep5_name(),
+            Expr::Or(exprs) => exprs
+                .iter()
+                .map(Expr::to_dep5)
+                .collect::<Vec<_>>()
+                .join(" or "),
+            Expr::And(exprs) => {
+                let sep = if exprs.iter().any(|e| matches!(e, Expr::Or(_))) {
+                    ", and "
+                } else {
+                    " and "
+                };
+                exprs
+                    .iter()
+                    .map(Expr::to_dep5)
+                    .collect::<Vec<_>>()
+                    .join(sep)
+            }
+        }
+    }
+
+    /// The individual licenses, each of which needs a license paragraph.
+    pub fn leaves(&self) -> Vec<&Leaf> {
+        match self {
+            Expr::License(leaf) => vec![leaf],
+            Expr::And(exprs) | Expr::Or(exprs) => exprs.iter().flat_map(Expr::leaves).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse;
+
+    #[test]
+    fn spdx_to_dep5() {
+        let dep5 = |s: &str| parse(s).unwrap().to_dep5();
+        assert_eq!(dep5("MIT OR Apache-2.0"), "MIT or Apache-2.0");
+        assert_eq!(dep5("MIT/Apache-2.0"), "MIT or Apache-2.0");
+        assert_eq!(dep5("mit or apache-2.0"), "MIT or Apache-2.0");
+        assert_eq!(dep5("GPL-2.0-or-later"), "GPL-2+");
+        assert_eq!(dep5("GPL-2.0+"), "GPL-2+");
+        assert_eq!(dep5("LGPL-2.1-only"), "LGPL-2.1");
+        assert_eq!(dep5("BSD-2-Clause-FreeBSD"), "BSD-2-clause");
+        assert_eq!(
+            dep5("Apache-2.0 WITH LLVM-exception"),
+            "Apache-2.0 with LLVM exception"
+        );
+        assert_eq!(
+            dep5("(MIT OR Apache-2.0) AND Unicode-DFS-2016"),
+            "MIT or Apache-2.0, and Unicode-DFS-2016"
+        );
+        assert_eq!(
+            dep5("MIT AND (Apache-2.0 OR (BSL-1.0 OR 0BSD))"),
+            "MIT, and Apache-2.0 or BSL-1.0 or 0BSD"
+        );
+        assert_eq!(
+            dep5("MIT AND BSD-3-Clause OR ISC"),
+            "MIT and BSD-3-clause or ISC"
+        );
+
+        let leaves = parse("(Apache-2.0 WITH LLVM-exception) OR MIT")
+            .unwrap()
+            .leaves()
+            .into_iter()
+            .map(|l| l.dep5_name())
+            .collect::<Vec<_>>();
+        assert_eq!(leaves, ["Apache-2.0 with LLVM exception", "MIT"]);
+
+        assert!(parse("MIT OR").is_err());
+        assert!(parse("(MIT").is_err());
+        assert!(parse("Apache License 2.0").is_err());
+        assert!(parse("MIT, Apache-2.0").is_err());
+    }
+}
@@ -1,5 +1,9 @@
-use super::{debian_copyright, get_licenses};
+use super::{
+    copyright_fromreleases, debian_copyright, get_licenses, group_files, is_redundant,
+    repository_clone_url, subdir_licenses, vcs_sha1, Files,
+};
 
+use std::fs;
 use std::path::Path;
 use std::rc::Rc;
 
@@ -9,27 +13,182 @@ use cargo::{
 };
 use toml::toml;
 
+/// A license expression, the License field that it becomes, and each of its
+/// licenses with whether debcargo knows the license text.
+type LicenseCase = (&'static str, &'static str, &'static [(&'static str, bool)]);
+
 #[test]
 fn check_get_licenses() {
-    let test_data: &[(&str, &[(&str, bool)])] = &[
-        ("AGPL-3.0", &[("AGPL-3.0", true)]),
-        ("AcmeCorp-1.0", &[("AcmeCorp-1.0", false)]),
-        ("AGPL-3.0-or-later", &[("AGPL-3.0-or-later", true)]),
-        ("Apache-2.0/MIT", &[("Apache-2.0", true), ("MIT", true)]),
-        ("Apache-2.0 or MIT", &[("Apache-2.0", true), ("MIT", true)]),
+    let test_data: &[LicenseCase] = &[
+        ("AGPL-3.0", "AGPL-3", &[("AGPL-3", true)]),
+        ("AcmeCorp-1.0", "AcmeCorp-1.0", &[("AcmeCorp-1.0", false)]),
+        ("AGPL-3.0-or-later", "AGPL-3+", &[("AGPL-3+", true)]),
+        (
+            "Apache-2.0/MIT",
+            "Apache-2.0 or MIT",
+            &[("Apache-2.0", true), ("MIT", true)],
+        ),
+        (
+            "Apache-2.0 or MIT",
+            "Apache-2.0 or MIT",
+            &[("Apache-2.0", true), ("MIT", true)],
+        ),
         (
             "FooBar-1.0 AND MIT",
+            "FooBar-1.0 and MIT",
             &[("FooBar-1.0", false), ("MIT", true)],
         ),
+        (
+            "Apache-2.0 WITH LLVM-exception OR BSL-1.0",
+            "Apache-2.0 with LLVM exception or BSL-1.0",
+            &[("Apache-2.0 with LLVM exception", true), ("BSL-1.0", true)],
+        ),
     ];
-    for (name, expected) in test_data {
-        let licenses = get_licenses(name).expect("getting licenses failed");
+    for (name, expected_expr, expected) in test_data {
+        let (expr, licenses) = get_licenses(name).expect("getting licenses failed");
+        assert_eq!(&expr, expected_expr);
         let found: Vec<_> = licenses
             .iter()
             .map(|l| (l.name.as_str(), !l.text.starts_with("FIXME")))
             .collect();
         assert_eq!(&found[..], &expected[..]);
     }
+    let (_, licenses) = get_licenses("Apache-2.0 WITH LLVM-exception").unwrap();
+    assert!(licenses[0].text.contains("LLVM Exceptions"));
+}
+
+#[test]
+fn check_subdir_licenses() {
+    let srcdir = tempfile::tempdir().unwrap();
+    let write = |path: &str, text: &str| {
+        let path = srcdir.path().join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, text).unwrap();
+    };
+    write("LICENSE-MIT", include_str!("../licenses/MIT"));
+    write("src/LICENSE", include_str!("../licenses/MIT"));
+    write(
+        "vendor/foo/COPYING",
+        include_str!("../licenses/BSD-3-Clause"),
+    );
+    write("vendor/bar/LICENSE.txt", "Do what you like.");
+
+    let (_, licenses) = get_licenses("MIT").unwrap();
+    let (files, new_licenses) = subdir_licenses(srcdir.path(), &licenses, &[]).unwrap();
+    let found: Vec<_> = files
+        .iter()
+        .map(|f| (f.files.as_str(), f.license.as_str()))
+        .collect();
+    assert_eq!(
+        found,
+        [
+            ("vendor/bar/*", "UNKNOWN-LICENSE; FIXME (overlay)"),
+            ("vendor/foo/*", "BSD-3-clause"),
+        ]
+    );
+    assert_eq!(new_licenses.len(), 1);
+    assert_eq!(new_licenses[0].name, "BSD-3-clause");
+}
+
+#[test]
+fn check_group_files() {
+    let notices = [
+        ("README", "2010 Bar"),
+        ("src/a.rs", "2015 Foo"),
+        ("src/b.rs", "2015 Foo"),
+        ("vendor/x/1.c", "2010 Bar"),
+        ("vendor/x/sub/2.c", "2010 Bar"),
+        ("vendor/y.c", "2015 Foo"),
+    ];
+    let files = || {
+        notices
+            .iter()
+            .map(|(f, c)| Files::new(f, &[c], "UNKNOWN-LICENSE; FIXME (overlay)", ""))
+            .collect()
+    };
+    let mut all_files = notices
+        .iter()
+        .map(|(f, _)| f.to_string())
+        .chain(["Cargo.toml".to_string()])
+        .collect::<Vec<_>>();
+    let grouped = group_files(files(), &all_files);
+    let found: Vec<_> = grouped
+        .iter()
+        .map(|f| (f.files.as_str(), f.copyright[0].as_str()))
+        .collect();
+    assert_eq!(
+        found,
+        [
+            ("vendor/x/*\n README", "2010 Bar"),
+            ("src/*\n vendor/y.c", "2015 Foo"),
+        ]
+    );
+
+    // files without notices are not claimed by a dir/* pattern
+    all_files.extend(["src/c.rs".to_string(), "vendor/x/sub/3.c".to_string()]);
+    let found: Vec<_> = group_files(files(), &all_files)
+        .into_iter()
+        .map(|f| (f.files, f.copyright[0].clone()))
+        .collect();
+    assert_eq!(
+        found,
+        [
+            (
+                "vendor/x/sub/2.c\n vendor/x/1.c\n README".to_string(),
+                "2010 Bar".to_string()
+            ),
+            (
+                "src/a.rs\n src/b.rs\n vendor/y.c".to_string(),
+                "2015 Foo".to_string()
+            ),
+        ]
+    );
+
+    let authors = ["Foo <foo@example.com>".to_string()];
+    let redundant: Vec<_> = grouped
+        .iter()
+        .map(|f| is_redundant(f, "MIT", &authors))
+        .collect();
+    assert_eq!(redundant, [false, true]);
+}
+
+#[test]
+fn check_offline_year_sources() {
+    assert_eq!(
+        repository_clone_url("https://github.com/BurntSushi/ripgrep/tree/master/crates/globset"),
+        "https://github.com/BurntSushi/ripgrep"
+    );
+    assert_eq!(
+        repository_clone_url("https://gitlab.com/foo/bar/-/tree/main/baz/"),
+        "https://gitlab.com/foo/bar"
+    );
+    assert_eq!(
+        repository_clone_url("https://github.com/serde-rs/serde/"),
+        "https://github.com/serde-rs/serde"
+    );
+
+    let srcdir = tempfile::tempdir().unwrap();
+    assert_eq!(vcs_sha1(srcdir.path()), None);
+    fs::write(
+        srcdir.path().join(".cargo_vcs_info.json"),
+        r#"{"git": {"sha1": "0123abcd"}, "path_in_vcs": "crates/foo"}"#,
+    )
+    .unwrap();
+    assert_eq!(vcs_sha1(srcdir.path()).as_deref(), Some("0123abcd"));
+
+    let releases: Vec<serde_json::Value> = [
+        r#"{"vers": "0.1.0", "pubtime": "2016-09-27T22:57:17Z"}"#,
+        r#"{"vers": "0.2.0"}"#,
+        r#"{"vers": "0.3.0", "pubtime": "2019-01-02T03:04:05Z"}"#,
+    ]
+    .iter()
+    .map(|r| serde_json::from_str(r).unwrap())
+    .collect();
+    assert_eq!(
+        copyright_fromreleases(&releases),
+        Some(("2016-2019,".to_string(), 2))
+    );
+    assert_eq!(copyright_fromreleases(&releases[1..2]), None);
 }
 
 #[test]
@@ -62,6 +221,7 @@ fn check_debian_copyright_authors() {
             "Jordan Doe",
             &[],
             (2000, 2020),
+            None,
             false,
         )
         .unwrap();
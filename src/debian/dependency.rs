@@ -5,7 +5,8 @@ use std::cmp;
 use std::fmt;
 
 use crate::config::{testing_ignore_debpolv, Config};
-use crate::debian::{self, control::base_deb_name, Package};
+use crate::crates::CrateInfo;
+use crate::debian::{self, control::base_deb_name};
 use crate::errors::*;
 
 #[derive(Eq, Clone)]
@@ -295,7 +296,7 @@ pub fn deb_dep(config: &Config, dep: &Dependency) -> Result<Vec<String>> // resu
     let req = semver::VersionReq::parse(&dep.version_req().to_string()).unwrap();
     let mut deps = Vec::new();
     for suffix in suffixes {
-        let base = format!("{}-{}", Package::pkg_prefix(), dep_dashed);
+        let base = format!("{}-{}", config.profile().package_prefix, dep_dashed);
         let mut vr = VRange::new();
         for p in &req.comparators {
             let op = coerce_unacceptable_predicate(dep, p, config.allow_prerelease_deps)?;
@@ -317,6 +318,32 @@ pub fn deb_deps(config: &Config, cdeps: &[Dependency]) -> Result<Vec<String>> //
     Ok(deps)
 }
 
+/// Translates Cargo dependencies into Debian build dependencies. Unlike
+/// `deb_deps`, a dependency that only applies on some architectures is
+/// restricted to those, e.g. `librust-foo-dev [amd64 x32]`.
+pub fn deb_build_deps(
+    config: &Config,
+    crate_info: &CrateInfo,
+    cdeps: &[Dependency],
+) -> Result<Vec<String>> {
+    let mut deps = Vec::new();
+    for dep in cdeps {
+        let arches = crate_info.dependency_arches(dep);
+        for d in deb_dep(config, dep)? {
+            deps.push(match &arches {
+                None => d,
+                Some(arches) => d
+                    .split('|')
+                    .map(|x| format!("{} [{}]", x.trim(), arches.join(" ")))
+                    .join(" | "),
+            });
+        }
+    }
+    deps.sort();
+    deps.dedup();
+    Ok(deps)
+}
+
 pub fn deb_dep_add_nocheck(x: &str) -> String {
     x.to_string()
         .split('|')
@@ -4,10 +4,10 @@ use std::io::{self, ErrorKind, Read, Seek, Write as IoWrite};
 #[cfg(unix)]
 use std::os::unix::fs::PermissionsExt;
 use std::path::{Path, PathBuf};
-use std::process::Command;
 use std::str::FromStr;
 
 use anyhow::format_err;
+use cargo::core::SourceId;
 use chrono::{self, Datelike};
 use flate2::read::GzDecoder;
 use flate2::write::GzEncoder;
@@ -17,20 +17,27 @@ use tar::{Archive, Builder};
 use tempfile;
 
 use crate::config::{package_field_for_feature, testing_ignore_debpolv, Config, PackageKey};
-use crate::crates::{show_dep, transitive_deps, CrateDepInfo, CrateInfo};
+use crate::crates::{diff, show_dep, transitive_deps, CrateDepInfo, CrateInfo};
 use crate::errors::*;
-use crate::util::{self, copy_tree, expect_success, get_transitive_val, traverse_depth};
+use crate::profile::Profile;
+use crate::util::{self, copy_tree, get_transitive_val, traverse_depth};
 
 use self::changelog::{ChangelogEntry, ChangelogIterator};
 use self::control::{base_deb_name, deb_upstream_version};
 use self::control::{Description, Package, PkgTest, Source};
-use self::copyright::debian_copyright;
-pub use self::dependency::{deb_dep_add_nocheck, deb_deps};
+use self::copyright::{debian_copyright, YearSources};
+pub use self::dependency::{deb_build_deps, deb_dep_add_nocheck, deb_deps};
+use self::syslibs::SysLib;
 
+pub mod arch;
+pub mod binaries;
 pub mod changelog;
 pub mod control;
 pub mod copyright;
 mod dependency;
+pub mod patches;
+pub mod syslibs;
+mod upstream;
 
 pub struct DebInfo {
     upstream_name: String,
@@ -48,8 +55,29 @@ pub struct DebInfo {
     orig_tarball_path: PathBuf,
 }
 
+/// Where a crate came from, for humans.
+fn crate_source_name(source_id: SourceId) -> String {
+    if source_id.is_default_registry() {
+        "crates.io".to_string()
+    } else if source_id.is_path() {
+        "local source".to_string()
+    } else if source_id.is_remote_registry() {
+        format!("registry {}", source_id.display_registry_name())
+    } else if source_id.is_registry() {
+        // not the path, which is specific to the machine that packaged it
+        "local registry".to_string()
+    } else {
+        "vendor directory".to_string()
+    }
+}
+
 impl DebInfo {
-    pub fn new(crate_info: &CrateInfo, debcargo_version: &str, semver_suffix: bool) -> Self {
+    pub fn new(
+        profile: &Profile,
+        crate_info: &CrateInfo,
+        debcargo_version: &str,
+        semver_suffix: bool,
+    ) -> Self {
         let upstream_name = crate_info.package_id().name().to_string();
         let name_dashed = base_deb_name(&upstream_name);
         let base_package_name = name_dashed.to_lowercase();
@@ -67,15 +95,11 @@ impl DebInfo {
         let deb_upstream_version = deb_upstream_version(crate_info.version());
         let package_source_dir = PathBuf::from(format!(
             "{}-{}-{}",
-            Source::pkg_prefix(),
-            package_name,
-            deb_upstream_version
+            profile.source_prefix, package_name, deb_upstream_version
         ));
         let orig_tarball_path = PathBuf::from(format!(
             "{}-{}_{}.orig.tar.gz",
-            Source::pkg_prefix(),
-            package_name,
-            deb_upstream_version
+            profile.source_prefix, package_name, deb_upstream_version
         ));
 
         DebInfo {
@@ -165,19 +189,14 @@ pub fn prepare_orig_tarball(
                 new_archive_append("Cargo.toml")?;
                 new_archive_append("Cargo.toml.orig")?;
             } else {
-                match crate_info.filter_path(&entry.path()?) {
-                    Err(e) => debcargo_bail!(e),
-                    Ok(r) => {
-                        if !r {
-                            new_archive.append(&entry.header().clone(), entry)?;
-                        } else {
-                            writeln!(
-                                io::stderr(),
-                                "Filtered out files from .orig.tar.gz: {:?}",
-                                &entry.path()?
-                            )?;
-                        }
-                    }
+                if !crate_info.is_excluded(&path) {
+                    new_archive.append(&entry.header().clone(), entry)?;
+                } else {
+                    writeln!(
+                        io::stderr(),
+                        "Filtered out files from .orig.tar.gz: {:?}",
+                        &path
+                    )?;
                 }
             }
         }
@@ -222,30 +241,12 @@ it's a maintenance burden. Use debcargo.toml instead."
     if tempdir.path().join("patches").join("series").exists() {
         // apply patches to Cargo.toml in case they exist, and re-read it
         let output_dir = &fs::canonicalize(output_dir)?;
-        let stderr = || {
-            // create a new owned handle to stderr
-            fs::OpenOptions::new()
-                .append(true)
-                .open("/dev/stderr")
-                .unwrap()
-        };
-        expect_success(
-            Command::new("quilt")
-                .stdout(stderr())
-                .current_dir(output_dir)
-                .env("QUILT_PATCHES", tempdir.path().join("patches"))
-                .args(["push", "--quiltrc=-", "-a"]),
-            "failed to apply patches using quilt",
-        );
-        crate_info.replace_manifest(&output_dir.join("Cargo.toml"))?;
-        expect_success(
-            Command::new("quilt")
-                .stdout(stderr())
-                .current_dir(output_dir)
-                .env("QUILT_PATCHES", tempdir.path().join("patches"))
-                .args(["pop", "--quiltrc=-", "-a"]),
-            "failed to unapply patches",
-        );
+        let applied = patches::push_all(&tempdir.path().join("patches"), output_dir)?;
+        let replaced = crate_info
+            .replace_manifest(&output_dir.join("Cargo.toml"))
+            .map(|_| ());
+        applied.revert()?;
+        replaced?;
     }
     Ok(tempdir)
 }
@@ -260,6 +261,7 @@ pub fn prepare_debian_folder(
     tempdir: &tempfile::TempDir,
     changelog_ready: bool,
     copyright_guess_harder: bool,
+    copyright_per_file: bool,
     overlay_write_back: bool,
 ) -> Result<()> {
     let mut create = fs::OpenOptions::new();
@@ -277,6 +279,47 @@ pub fn prepare_debian_folder(
         .map(String::as_str)
         .collect();
 
+    // debian/compat is replaced by debhelper-compat in Build-Depends, and
+    // debhelper refuses to build if both are present
+    let compat_path = tempdir.path().join("compat");
+    let overlay_compat = if compat_path.exists() {
+        let level = fs::read_to_string(&compat_path)?;
+        let level = match level.trim().parse::<u32>() {
+            Ok(l) => l,
+            Err(_) => debcargo_bail!("invalid debhelper compat level in debian/compat: {}", level),
+        };
+        fs::remove_file(&compat_path)?;
+        let hint = tempdir.path().join("compat.debcargo.hint");
+        if hint.exists() {
+            fs::remove_file(hint)?;
+        }
+        Some(level)
+    } else {
+        None
+    };
+    let compat_level = match (config.debhelper_compat(), overlay_compat) {
+        (Some(level), Some(old)) => {
+            debcargo_warn!(
+                "debian/compat in the overlay is obsolete; ignoring its level {} in favour \
+                 of debhelper_compat = {} in the config, remove it",
+                old,
+                level
+            );
+            level
+        }
+        (Some(level), None) => level,
+        (None, Some(level)) => {
+            debcargo_warn!(
+                "debian/compat in the overlay is obsolete, as debhelper-compat is now in \
+                 Build-Depends; remove it, and set debhelper_compat = {} in [source] if \
+                 the package needs that level",
+                level
+            );
+            level
+        }
+        (None, None) => config.profile().debhelper_compat,
+    };
+
     let mut new_hints = vec![];
     let mut file = |name: &str| {
         let path = tempdir.path();
@@ -309,12 +352,6 @@ pub fn prepare_debian_folder(
         )?;
     }
 
-    // debian/compat
-    {
-        let mut compat = file("compat")?;
-        writeln!(compat, "12")?;
-    }
-
     // debian/copyright
     {
         let mut copyright = io::BufWriter::new(file("copyright")?);
@@ -329,6 +366,15 @@ pub fn prepare_debian_folder(
                 Err(_) => (last, last),
             }
         };
+        let year_sources = if copyright_guess_harder {
+            Some(YearSources {
+                offline: crate_info.offline(),
+                upstream_clone: config.upstream_clone(config_path),
+                releases: crate_info.local_index_entries(),
+            })
+        } else {
+            None
+        };
         let dep5_copyright = debian_copyright(
             output_dir,
             crate_info.manifest(),
@@ -336,7 +382,8 @@ pub fn prepare_debian_folder(
             maintainer,
             &uploaders,
             year_range,
-            copyright_guess_harder,
+            year_sources.as_ref(),
+            copyright_per_file,
         )?;
         write!(copyright, "{}", dep5_copyright)?;
     }
@@ -344,32 +391,44 @@ pub fn prepare_debian_folder(
     // debian/watch
     {
         let mut watch = file("watch")?;
-        match config.crate_src_path(config_path) {
-            Some(_) => write!(watch, "FIXME add uscan directive for local crate")?,
-            None => {
-                let uscan_version_pattern = deb_info
-                    .uscan_version_pattern
-                    .as_ref()
-                    .map_or_else(|| "@ANY_VERSION@".to_string(), |ref s| s.to_string());
-                writeln!(watch, "version=4")?;
-                writeln!(
-                    watch,
-                    r"opts=filenamemangle=s/.*\/(.*)\/download/{name}-$1\.tar\.gz/g,\",
-                    name = upstream_name
-                )?;
-                writeln!(
-                    watch,
-                    r"uversionmangle=s/(\d)[_\.\-\+]?((RC|rc|pre|dev|beta|alpha)\d*)$/$1~$2/ \"
-                )?;
-                writeln!(
-                    watch,
-                    "https://qa.debian.org/cgi-bin/fakeupstream.cgi?upstream=crates.io/{name} \
+        let source_id = crate_info.source_id();
+        if source_id.is_path() {
+            write!(watch, "FIXME add uscan directive for local crate")?;
+        } else if !source_id.is_default_registry() {
+            write!(
+                watch,
+                "FIXME add uscan directive for crate from {}",
+                crate_source_name(source_id)
+            )?;
+        } else {
+            let uscan_version_pattern = deb_info
+                .uscan_version_pattern
+                .as_ref()
+                .map_or_else(|| "@ANY_VERSION@".to_string(), |ref s| s.to_string());
+            writeln!(watch, "version=4")?;
+            writeln!(
+                watch,
+                r"opts=filenamemangle=s/.*\/(.*)\/download/{name}-$1\.tar\.gz/g,\",
+                name = upstream_name
+            )?;
+            writeln!(
+                watch,
+                r"uversionmangle=s/(\d)[_\.\-\+]?((RC|rc|pre|dev|beta|alpha)\d*)$/$1~$2/ \"
+            )?;
+            writeln!(
+                watch,
+                "https://qa.debian.org/cgi-bin/fakeupstream.cgi?upstream=crates.io/{name} \
                      .*/crates/{name}/{version_pattern}/download",
-                    name = upstream_name,
-                    version_pattern = uscan_version_pattern
-                )?;
-            }
-        };
+                name = upstream_name,
+                version_pattern = uscan_version_pattern
+            )?;
+        }
+    }
+
+    // debian/upstream/metadata
+    if let Some(metadata) = upstream::upstream_metadata(crate_info.metadata(), output_dir) {
+        let mut upstream_metadata = file("upstream/metadata")?;
+        write!(upstream_metadata, "{}", metadata)?;
     }
 
     // debian/source/format
@@ -380,8 +439,14 @@ pub fn prepare_debian_folder(
     }
 
     // debian/control & debian/tests/control
-    let (source, has_dev_depends, default_test_broken) =
-        prepare_debian_control(deb_info, crate_info, config, &mut file)?;
+    let (source, has_dev_depends, default_test_broken) = prepare_debian_control(
+        deb_info,
+        crate_info,
+        config,
+        config_path,
+        compat_level,
+        &mut file,
+    )?;
 
     // for testing only, debian/debcargo_testing_bin/env
     if testing_ignore_debpolv() {
@@ -458,10 +523,7 @@ echo "debcargo testing: suppressing dh-cargo-built-using";;
     // debian/changelog
     if !changelog_ready {
         let author = control::get_deb_author()?;
-        let crate_src = match config.crate_src_path(config_path) {
-            Some(_) => "local source",
-            None => "crates.io",
-        };
+        let crate_src = crate_source_name(crate_info.source_id());
         let autogenerated_item = format!(
             "  * Package {} {} from {} using debcargo {}",
             &crate_name,
@@ -471,12 +533,22 @@ echo "debcargo testing: suppressing dh-cargo-built-using";;
         );
         let autogenerated_re = Regex::new(&format!(
             r"^  \* Package (.*) (.*) from {} using debcargo (.*)$",
-            &crate_src
+            regex::escape(&crate_src)
         ))
         .unwrap();
 
         // Special-case d/changelog:
         let (mut changelog, changelog_data) = changelog_or_new(tempdir.path())?;
+        let last_version = changelog::last_packaged_version(&changelog_data)?;
+        let current_facts = diff::ManifestFacts::from_crate(crate_info);
+        let last_facts = match last_version {
+            Some(ref v) if v != deb_info.deb_upstream_version() => load_facts(crate_info, v),
+            _ => None,
+        };
+        let summary_items = match last_facts {
+            Some(ref old) => diff::ManifestDiff::new(old, &current_facts).changelog_items(),
+            None => vec![],
+        };
         let (changelog_old, mut changelog_items, deb_version_suffix) = {
             let ver_bump = &|e: &Option<&str>| -> Result<Option<String>> {
                 Ok(match e {
@@ -498,25 +570,64 @@ echo "debcargo testing: suppressing dh-cargo-built-using";;
                 Some(x) if x.contains(changelog::DEFAULT_DIST) => {
                     let mut e = ChangelogEntry::from_str(x)?;
                     if author == e.maintainer {
-                        if let Some(pos) = e.items.iter().position(|x| autogenerated_re.is_match(x))
-                        {
-                            e.items[pos] = autogenerated_item;
-                        } else {
-                            e.items.push(autogenerated_item);
-                        }
+                        // Replace the summary that we generated for this entry
+                        // before, leaving any items written by hand alone.
+                        let entry_version = e.upstream_version();
+                        // only look for a summary if there is more than the
+                        // item that says which version is packaged
+                        let has_items = e
+                            .items
+                            .iter()
+                            .any(|x| !x.trim().is_empty() && !autogenerated_re.is_match(x));
+                        let previous_summary = match last_version {
+                            Some(ref v) if *v == entry_version => vec![],
+                            Some(_) if !has_items => vec![],
+                            Some(_) if entry_version == deb_info.deb_upstream_version() => {
+                                summary_items.clone()
+                            }
+                            Some(ref v) => {
+                                let old = match last_facts {
+                                    Some(ref old) => Some(old.clone()),
+                                    None => load_facts(crate_info, v),
+                                };
+                                match (old, load_facts(crate_info, &entry_version)) {
+                                    (Some(old), Some(new)) => {
+                                        diff::ManifestDiff::new(&old, &new).changelog_items()
+                                    }
+                                    _ => vec![],
+                                }
+                            }
+                            None => vec![],
+                        };
+                        e.items.retain(|x| !previous_summary.contains(x));
+                        let pos = match e.items.iter().position(|x| autogenerated_re.is_match(x)) {
+                            Some(pos) => {
+                                e.items[pos] = autogenerated_item;
+                                pos + 1
+                            }
+                            None => {
+                                e.items.push(autogenerated_item);
+                                e.items.len()
+                            }
+                        };
+                        e.items.splice(pos..pos, summary_items);
                     } else {
                         // If unreleased changelog is by someone else, preserve their entries
+                        let n = summary_items.len();
                         e.items.insert(0, autogenerated_item);
-                        e.items.insert(1, "".to_string());
+                        e.items.splice(1..1, summary_items);
+                        e.items.insert(n + 1, "".to_string());
                         let ename = e.maintainer_name();
-                        e.items.insert(2, format!("  [ {} ]", ename));
+                        e.items.insert(n + 2, format!("  [ {} ]", ename));
                     }
                     (&changelog_data[x.len()..], e.items, ver_bump(&chit.next())?)
                 }
                 // Otherwise prepend a new entry to the existing entries
                 _ => (
                     changelog_data.as_str(),
-                    vec![autogenerated_item],
+                    std::iter::once(autogenerated_item)
+                        .chain(summary_items)
+                        .collect(),
                     ver_bump(&e1)?,
                 ),
             }
@@ -582,12 +693,15 @@ fn prepare_debian_control<F: FnMut(&str) -> std::result::Result<std::fs::File, s
     deb_info: &DebInfo,
     crate_info: &CrateInfo,
     config: &Config,
+    config_path: Option<&Path>,
+    compat_level: u32,
     mut file: F,
 ) -> Result<(Source, bool, bool)> {
     let crate_name = crate_info.crate_name();
     let deb_upstream_version = deb_info.deb_upstream_version();
     let base_pkgname = deb_info.base_package_name();
     let name_suffix = deb_info.name_suffix();
+    let profile = config.profile();
 
     let lib = crate_info.is_lib();
     let mut bins = crate_info.get_binary_targets();
@@ -644,11 +758,29 @@ fn prepare_debian_control<F: FnMut(&str) -> std::result::Result<std::fs::File, s
         }
     };
 
+    let (syslib_deps, unknown_syslib) = match config
+        .syslib_map(config_path)?
+        .lookup(crate_name, crate_info.links())
+    {
+        SysLib::None => (vec![], None),
+        SysLib::Packages(p) => (p, None),
+        SysLib::Unknown(links) => {
+            debcargo_warn!(
+                "No known Debian package for the native library \"{}\" that {} links to",
+                links,
+                crate_name
+            );
+            (vec![], Some(links))
+        }
+    };
+
     let rustc = rustc_dep(&crate_info.rust_version());
     let build_deps = {
-        let build_deps = ["debhelper (>= 12)", "dh-cargo (>= 25)"]
-            .iter()
-            .map(|x| x.to_string());
+        let build_deps = [
+            format!("debhelper-compat (= {})", compat_level),
+            format!("dh-cargo (>= {})", profile.dh_cargo),
+        ]
+        .into_iter();
         // note: please keep this in sync with build_order::dep_features
         let (default_features, default_deps) = transitive_deps(&features_with_deps, "default")?;
         //debcargo_info!("default_features: {:?}", default_features);
@@ -664,7 +796,8 @@ fn prepare_debian_control<F: FnMut(&str) -> std::result::Result<std::fs::File, s
             "libstd-rust-dev".into(),
         ]
         .into_iter()
-        .chain(deb_deps(config, &default_deps)?)
+        .chain(deb_build_deps(config, crate_info, &default_deps)?)
+        .chain(syslib_deps.iter().cloned())
         .chain(extra_override_deps);
         if !bins.is_empty() {
             build_deps.chain(build_deps_extra).collect()
@@ -677,9 +810,11 @@ fn prepare_debian_control<F: FnMut(&str) -> std::result::Result<std::fs::File, s
     };
     let test_deps: Vec<String> = Some(rustc).into_iter().chain(dev_depends).collect();
     let mut source = Source::new(
+        profile,
         base_pkgname,
         name_suffix,
         crate_name,
+        deb_upstream_version,
         if let Some(ref home) = meta.homepage {
             home
         } else {
@@ -694,11 +829,45 @@ fn prepare_debian_control<F: FnMut(&str) -> std::result::Result<std::fs::File, s
         } else {
             "no".to_string()
         },
+        config.vcs_git().unwrap_or(&profile.vcs_git),
+        config.vcs_browser().unwrap_or(&profile.vcs_browser),
     )?;
 
     // If source overrides are present update related parts.
     source.apply_overrides(config);
 
+    if let Some(links) = unknown_syslib {
+        source.add_comment(&format!(
+            "FIXME (syslib_map) no known Debian package for the native library that \
+             Cargo.toml links to (links = \"{}\"); add it to a syslib_map file, or set \
+             packages.lib.depends and source.build_depends",
+            links
+        ));
+    }
+
+    let non_debian_deps = crate_info
+        .non_debian_dependencies()
+        .into_iter()
+        .map(|d| d.package_name().as_str())
+        .collect::<BTreeSet<_>>();
+    if !non_debian_deps.is_empty() {
+        source.add_comment(&format!(
+            "Dependencies only for targets that are not Debian architectures were \
+             dropped: {}",
+            non_debian_deps.into_iter().collect::<Vec<_>>().join(", ")
+        ));
+    }
+    if crate_info
+        .dependencies()
+        .iter()
+        .any(|d| matches!(crate_info.dependency_arches(d), Some(a) if !a.is_empty()))
+    {
+        source.add_comment(
+            "Some dependencies are specific to some targets, so the build dependencies \
+             on them are restricted to the matching architectures.",
+        );
+    }
+
     let mut control = io::BufWriter::new(file("control")?);
     write!(control, "{}", source)?;
 
@@ -730,6 +899,7 @@ fn prepare_debian_control<F: FnMut(&str) -> std::result::Result<std::fs::File, s
             testctl,
             "{}",
             PkgTest::new(
+                profile,
                 source.name(),
                 crate_name,
                 "@",
@@ -745,72 +915,7 @@ fn prepare_debian_control<F: FnMut(&str) -> std::result::Result<std::fs::File, s
         )?;
 
         // begin transforming dependencies
-        let working_features_with_deps = features_with_deps.clone();
-        let working_features_with_deps = {
-            let mut working_features_with_deps = working_features_with_deps;
-            // Detect corner case with feature naming regarding _ vs -.
-            // Debian does not support _ in package names. Cargo automatically
-            // converts - in crate names to _, but features (including optional
-            // dependencies) can have both _ and -.
-            let potential_corner_case = working_features_with_deps
-                .keys()
-                .filter(|x| base_deb_name(x).as_str() != **x)
-                .cloned()
-                .collect::<Vec<_>>();
-            for f in potential_corner_case {
-                let f_ = base_deb_name(f);
-                if let Some((df1, dd1)) = working_features_with_deps.remove(f_.as_str()) {
-                    // merge dependencies of f_ and f
-                    working_features_with_deps
-                        .entry(f)
-                        .and_modify(|(df0, dd0)| {
-                            let mut df = BTreeSet::from_iter(df0.drain(..));
-                            df.extend(df1);
-                            df.remove(f_.as_str());
-                            df.remove(f);
-                            let mut dd: HashSet<cargo::core::Dependency> =
-                                HashSet::from_iter(dd0.drain(..));
-                            dd.extend(dd1);
-                            df0.extend(df);
-                            dd0.extend(dd);
-                        });
-                    // go through other feature deps and change f_ to f
-                    for (_, (df, _)) in working_features_with_deps.iter_mut() {
-                        for feat in df.iter_mut() {
-                            if *feat == f_.as_str() {
-                                *feat = f;
-                            }
-                        }
-                    }
-                    // check we didn't create a cycle in features
-                    let dep_feats = traverse_depth(
-                        &|k: &&'static str| working_features_with_deps.get(k).map(|x| &x.0),
-                        f,
-                    );
-                    if dep_feats.contains(f) {
-                        log::debug!("transitive deps of feature {}: {:?}", f, dep_feats);
-                        debcargo_bail!(
-                            "Tried to merge features {} and {} as they are not representable separately\n\
-                             in Debian, but this resulted in a feature cycle. You need to manually patch the package.", f, f_);
-                    } else {
-                        debcargo_warn!(
-                            "Merged features {} and {} as they are not representable separately in Debian.\n\
-                             We checked that this does not break the package in an obvious way (feature cycle), however\n\
-                             if there is a more sophisticated breakage, you'll have to manually patch those \
-                             features instead.", f, f_);
-                    }
-                }
-            }
-            working_features_with_deps
-        };
-        log::trace!(
-            "working_features_with_deps: {:?}",
-            working_features_with_deps
-                .iter()
-                .map(|(&f, (ff, dd))| { (f, (ff, dd.iter().map(show_dep).collect::<Vec<_>>())) })
-                .collect::<Vec<_>>()
-        );
-        let (mut provides, reduced_features_with_deps) = if config.collapse_features {
+        if config.collapse_features {
             debcargo_warn!(
                 "You are using the collapse_features work-around, which makes the resulting"
             );
@@ -867,10 +972,9 @@ fn prepare_debian_control<F: FnMut(&str) -> std::result::Result<std::fs::File, s
                 "cargo; but if collapse_features is used then package A+AX+AY would cyclicly"
             );
             debcargo_warn!("depend on package B+BX+BY.");
-            collapse_features(working_features_with_deps)
-        } else {
-            reduce_provides(working_features_with_deps)
-        };
+        }
+        let (mut provides, reduced_features_with_deps) =
+            feature_packages(config, features_with_deps.clone())?;
         log::trace!(
             "reduced_features_with_deps: {:?}",
             reduced_features_with_deps
@@ -933,6 +1037,7 @@ fn prepare_debian_control<F: FnMut(&str) -> std::result::Result<std::fs::File, s
                 )
             };
             let mut package = Package::new(
+                profile,
                 base_pkgname,
                 name_suffix,
                 crate_info.version(),
@@ -950,7 +1055,14 @@ fn prepare_debian_control<F: FnMut(&str) -> std::result::Result<std::fs::File, s
                     Some(feature)
                 },
                 f_deps,
-                deb_deps(config, &o_deps)?,
+                if feature.is_empty() {
+                    deb_deps(config, &o_deps)?
+                        .into_iter()
+                        .chain(syslib_deps.iter().cloned())
+                        .collect()
+                } else {
+                    deb_deps(config, &o_deps)?
+                },
                 f_provides.clone(),
                 if feature.is_empty() {
                     recommends.clone()
@@ -1011,6 +1123,7 @@ fn prepare_debian_control<F: FnMut(&str) -> std::result::Result<std::fs::File, s
                 // deps
                 let test_depends = generate_test_dependencies(f, &feature_deps, config, &test_deps);
                 let pkgtest = PkgTest::new(
+                    profile,
                     package.name(),
                     crate_name,
                     f,
@@ -1086,6 +1199,90 @@ fn generate_test_dependencies(
         .collect::<Vec<_>>()
 }
 
+/// Merge features whose names differ only by `_` vs `-`, since Debian
+/// package names cannot tell them apart.
+fn merge_corner_case_features(
+    mut working_features_with_deps: CrateDepInfo,
+) -> Result<CrateDepInfo> {
+    // Detect corner case with feature naming regarding _ vs -.
+    // Debian does not support _ in package names. Cargo automatically
+    // converts - in crate names to _, but features (including optional
+    // dependencies) can have both _ and -.
+    let potential_corner_case = working_features_with_deps
+        .keys()
+        .filter(|x| base_deb_name(x).as_str() != **x)
+        .cloned()
+        .collect::<Vec<_>>();
+    for f in potential_corner_case {
+        let f_ = base_deb_name(f);
+        if let Some((df1, dd1)) = working_features_with_deps.remove(f_.as_str()) {
+            // merge dependencies of f_ and f
+            working_features_with_deps
+                .entry(f)
+                .and_modify(|(df0, dd0)| {
+                    let mut df = BTreeSet::from_iter(df0.drain(..));
+                    df.extend(df1);
+                    df.remove(f_.as_str());
+                    df.remove(f);
+                    let mut dd: HashSet<cargo::core::Dependency> =
+                        HashSet::from_iter(dd0.drain(..));
+                    dd.extend(dd1);
+                    df0.extend(df);
+                    dd0.extend(dd);
+                });
+            // go through other feature deps and change f_ to f
+            for (_, (df, _)) in working_features_with_deps.iter_mut() {
+                for feat in df.iter_mut() {
+                    if *feat == f_.as_str() {
+                        *feat = f;
+                    }
+                }
+            }
+            // check we didn't create a cycle in features
+            let dep_feats = traverse_depth(
+                &|k: &&'static str| working_features_with_deps.get(k).map(|x| &x.0),
+                f,
+            );
+            if dep_feats.contains(f) {
+                log::debug!("transitive deps of feature {}: {:?}", f, dep_feats);
+                debcargo_bail!(
+                    "Tried to merge features {} and {} as they are not representable separately\n\
+                     in Debian, but this resulted in a feature cycle. You need to manually patch the package.", f, f_);
+            } else {
+                debcargo_warn!(
+                    "Merged features {} and {} as they are not representable separately in Debian.\n\
+                     We checked that this does not break the package in an obvious way (feature cycle), however\n\
+                     if there is a more sophisticated breakage, you'll have to manually patch those \
+                     features instead.", f, f_);
+            }
+        }
+    }
+    Ok(working_features_with_deps)
+}
+
+/// Group features into binary packages, the same way as the generated
+/// debian/control does. Returns the features that each package provides
+/// besides its own, keyed by the feature the package is named after, and the
+/// dependencies of each such feature.
+pub fn feature_packages(
+    config: &Config,
+    features_with_deps: CrateDepInfo,
+) -> Result<(BTreeMap<&'static str, Vec<&'static str>>, CrateDepInfo)> {
+    let working_features_with_deps = merge_corner_case_features(features_with_deps)?;
+    log::trace!(
+        "working_features_with_deps: {:?}",
+        working_features_with_deps
+            .iter()
+            .map(|(&f, (ff, dd))| { (f, (ff, dd.iter().map(show_dep).collect::<Vec<_>>())) })
+            .collect::<Vec<_>>()
+    );
+    Ok(if config.collapse_features {
+        collapse_features(working_features_with_deps)
+    } else {
+        reduce_provides(working_features_with_deps)
+    })
+}
+
 fn collapse_features(
     orig_features_with_deps: CrateDepInfo,
 ) -> (BTreeMap<&'static str, Vec<&'static str>>, CrateDepInfo) {
@@ -1204,6 +1401,24 @@ mod test {
     }
 }
 
+/// The facts of another version of the crate, to summarize what changed. Not
+/// being able to load it, e.g. when offline and it is not in the cargo cache,
+/// is not fatal.
+fn load_facts(crate_info: &CrateInfo, version: &str) -> Option<diff::ManifestFacts> {
+    match diff::load_version(crate_info, version) {
+        Ok(other) => other.as_ref().map(diff::ManifestFacts::from_crate),
+        Err(e) => {
+            debcargo_warn!(
+                "Could not load {} {} to summarize what changed: {}",
+                crate_info.crate_name(),
+                version,
+                e
+            );
+            None
+        }
+    }
+}
+
 fn changelog_or_new(tempdir: &Path) -> Result<(fs::File, String)> {
     let mut changelog = fs::OpenOptions::new()
         .read(true)
@@ -0,0 +1,372 @@
+use anyhow::{format_err, Context};
+use regex::Regex;
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Component, Path, PathBuf};
+
+use crate::errors::*;
+
+/// One line of a quilt series file.
+#[derive(Debug, PartialEq, Eq)]
+pub struct SeriesEntry {
+    pub name: String,
+    /// Number of leading path components to strip, as in `patch -pN`.
+    pub strip: usize,
+}
+
+#[derive(Debug)]
+struct Hunk {
+    header: String,
+    old_start: usize,
+    old_lines: Vec<String>,
+    new_lines: Vec<String>,
+}
+
+/// The part of a patch that applies to a single file.
+#[derive(Debug)]
+pub struct FilePatch {
+    /// None if the file is created by this patch.
+    old_path: Option<PathBuf>,
+    /// None if the file is deleted by this patch.
+    new_path: Option<PathBuf>,
+    /// Whether old_path is removed if it differs from new_path, as for a
+    /// "rename from" in a git diff. Otherwise it is copied to new_path.
+    rename: bool,
+    hunks: Vec<Hunk>,
+}
+
+/// Patches applied to a source tree, along with the original contents of
+/// every file they touched so that they can be reverted.
+pub struct AppliedPatches {
+    root: PathBuf,
+    originals: BTreeMap<PathBuf, Option<Vec<u8>>>,
+}
+
+pub fn read_series(patches_dir: &Path) -> Result<Vec<SeriesEntry>> {
+    let series = fs::read_to_string(patches_dir.join("series"))?;
+    let mut entries = Vec::new();
+    for line in series.lines() {
+        let mut words = line.split_whitespace();
+        let name = match words.next() {
+            None => continue,
+            Some(w) if w.starts_with('#') => continue,
+            Some(w) => w.to_string(),
+        };
+        let mut strip = 1;
+        for opt in words {
+            if opt.starts_with('#') {
+                break;
+            } else if let Some(n) = opt.strip_prefix("-p") {
+                strip = n
+                    .parse()
+                    .with_context(|| format!("bad option {} for patch {} in series", opt, name))?;
+            } else {
+                debcargo_bail!("unsupported option {} for patch {} in series", opt, name);
+            }
+        }
+        entries.push(SeriesEntry { name, strip });
+    }
+    Ok(entries)
+}
+
+fn parse_patch_path(s: &str, strip: usize) -> Result<Option<PathBuf>> {
+    // drop any trailing timestamp
+    let s = s.split('\t').next().unwrap().trim_end();
+    if s == "/dev/null" {
+        return Ok(None);
+    }
+    let path = Path::new(s)
+        .components()
+        .filter(|c| *c != Component::CurDir)
+        .skip(strip)
+        .collect::<PathBuf>();
+    if path.as_os_str().is_empty() {
+        debcargo_bail!("path {} has fewer than {} components to strip", s, strip);
+    }
+    if path
+        .components()
+        .any(|c| !matches!(c, Component::Normal(_)))
+    {
+        debcargo_bail!("refusing to patch path outside of the source tree: {}", s);
+    }
+    Ok(Some(path))
+}
+
+/// A rename or copy from the extended header of a git diff, which may have
+/// no hunks at all.
+#[derive(Default)]
+struct GitHeader {
+    rename: bool,
+    from: Option<PathBuf>,
+    to: Option<PathBuf>,
+}
+
+impl GitHeader {
+    fn into_file_patch(self) -> Option<FilePatch> {
+        Some(FilePatch {
+            old_path: Some(self.from?),
+            new_path: Some(self.to?),
+            rename: self.rename,
+            hunks: vec![],
+        })
+    }
+}
+
+/// Parse a unified diff, as generated by `diff -u`, `git diff` or quilt.
+pub fn parse_patch(text: &str, strip: usize) -> Result<Vec<FilePatch>> {
+    let hunk_re = Regex::new(r"^@@ -(\d+)(?:,(\d+))? \+(\d+)(?:,(\d+))? @@").unwrap();
+    let lines = text.lines().collect::<Vec<_>>();
+    let mut file_patches = Vec::new();
+    let mut git_header = GitHeader::default();
+    // the paths in git's extended headers have no a/ or b/ prefix
+    let git_path = |s: &str| parse_patch_path(s, strip.saturating_sub(1));
+    let mut i = 0;
+    while i < lines.len() {
+        if lines[i].starts_with("diff --git ") {
+            file_patches.extend(std::mem::take(&mut git_header).into_file_patch());
+        } else if let Some(from) = lines[i].strip_prefix("rename from ") {
+            git_header.rename = true;
+            git_header.from = git_path(from)?;
+        } else if let Some(from) = lines[i].strip_prefix("copy from ") {
+            git_header.from = git_path(from)?;
+        } else if let Some(to) = lines[i]
+            .strip_prefix("rename to ")
+            .or_else(|| lines[i].strip_prefix("copy to "))
+        {
+            git_header.to = git_path(to)?;
+        }
+        if !(lines[i].starts_with("--- ")
+            && i + 1 < lines.len()
+            && lines[i + 1].starts_with("+++ "))
+        {
+            // anything outside of a file patch is commentary
+            i += 1;
+            continue;
+        }
+        let old_path = parse_patch_path(&lines[i][4..], strip)?;
+        let new_path = parse_patch_path(&lines[i + 1][4..], strip)?;
+        let rename = std::mem::take(&mut git_header).rename;
+        i += 2;
+
+        let mut hunks = Vec::new();
+        while i < lines.len() {
+            let m = match hunk_re.captures(lines[i]) {
+                Some(m) => m,
+                None => break,
+            };
+            let num = |j: usize| m.get(j).map_or(Ok(1), |x| x.as_str().parse::<usize>());
+            let header = lines[i].to_string();
+            let old_start = num(1)?;
+            let old_len = num(2)?;
+            let new_len = num(4)?;
+            i += 1;
+
+            let mut old_lines: Vec<String> = Vec::new();
+            let mut new_lines: Vec<String> = Vec::new();
+            while old_lines.len() < old_len || new_lines.len() < new_len {
+                let line = *lines
+                    .get(i)
+                    .ok_or_else(|| format_err!("patch ended in the middle of hunk {}", header))?;
+                // some tools strip the single space from blank context lines
+                let (kind, content) = match line.chars().next() {
+                    None => (' ', ""),
+                    Some(c) => (c, &line[c.len_utf8()..]),
+                };
+                let content = format!("{}\n", content);
+                let (in_old, in_new) = match kind {
+                    ' ' => (true, true),
+                    '-' => (true, false),
+                    '+' => (false, true),
+                    _ => debcargo_bail!("malformed line in hunk {}: {}", header, line),
+                };
+                if in_old {
+                    old_lines.push(content.clone());
+                }
+                if in_new {
+                    new_lines.push(content);
+                }
+                if old_lines.len() > old_len || new_lines.len() > new_len {
+                    debcargo_bail!("hunk {} is longer than its header says", header);
+                }
+                i += 1;
+                if matches!(lines.get(i), Some(l) if l.starts_with('\\')) {
+                    // "\ No newline at end of file" applies to the line before it
+                    if in_old {
+                        old_lines.last_mut().unwrap().pop();
+                    }
+                    if in_new {
+                        new_lines.last_mut().unwrap().pop();
+                    }
+                    i += 1;
+                }
+            }
+            hunks.push(Hunk {
+                header,
+                old_start,
+                old_lines,
+                new_lines,
+            });
+        }
+        file_patches.push(FilePatch {
+            old_path,
+            new_path,
+            rename,
+            hunks,
+        });
+    }
+    file_patches.extend(git_header.into_file_patch());
+    Ok(file_patches)
+}
+
+impl FilePatch {
+    /// Apply all hunks to the given file contents. Like patch(1), a hunk may
+    /// be applied at an offset from its stated position if the surrounding
+    /// lines have moved, but no fuzz is allowed.
+    pub fn apply(&self, content: &str) -> Result<String> {
+        let mut lines = content
+            .split_inclusive('\n')
+            .map(String::from)
+            .collect::<Vec<_>>();
+        let mut offset: isize = 0;
+        let mut min_pos = 0;
+        for (n, hunk) in self.hunks.iter().enumerate() {
+            let old_len = hunk.old_lines.len();
+            // an empty hunk is considered to start after its stated line
+            let stated = if old_len == 0 {
+                hunk.old_start
+            } else {
+                hunk.old_start.saturating_sub(1)
+            };
+            let expected = (stated as isize + offset).max(min_pos as isize) as usize;
+            let matches_at = |pos: usize| {
+                pos >= min_pos
+                    && pos + old_len <= lines.len()
+                    && lines[pos..pos + old_len] == hunk.old_lines[..]
+            };
+            let pos = (0..=lines.len())
+                .flat_map(|d| [expected.checked_add(d), expected.checked_sub(d)])
+                .flatten()
+                .find(|&pos| matches_at(pos))
+                .ok_or_else(|| {
+                    format_err!(
+                        "hunk #{} ({}) FAILED at line {}",
+                        n + 1,
+                        hunk.header,
+                        hunk.old_start
+                    )
+                })?;
+            if pos != expected {
+                log::debug!(
+                    "hunk #{} succeeded at line {} (offset {} lines)",
+                    n + 1,
+                    pos + 1,
+                    pos as isize - expected as isize
+                );
+            }
+            lines.splice(pos..pos + old_len, hunk.new_lines.iter().cloned());
+            offset += (pos as isize - expected as isize) + hunk.new_lines.len() as isize
+                - old_len as isize;
+            min_pos = pos + hunk.new_lines.len();
+        }
+        Ok(lines.concat())
+    }
+}
+
+impl AppliedPatches {
+    /// Restore every touched file to its state before the patches were applied.
+    pub fn revert(self) -> Result<()> {
+        for (path, original) in self.originals {
+            let path = self.root.join(path);
+            match original {
+                Some(data) => fs::write(&path, data)?,
+                None => {
+                    if path.exists() {
+                        fs::remove_file(&path)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Read a file of the tree, remembering its original contents the first
+    /// time that it is touched.
+    fn read(&mut self, path: &Path) -> Result<Option<Vec<u8>>> {
+        let data = match fs::read(self.root.join(path)) {
+            Ok(data) => Some(data),
+            Err(e) if e.kind() == ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+        self.originals
+            .entry(path.to_path_buf())
+            .or_insert_with(|| data.clone());
+        Ok(data)
+    }
+
+    fn apply(&mut self, patches_dir: &Path, entry: &SeriesEntry) -> Result<()> {
+        let text = fs::read_to_string(patches_dir.join(&entry.name))
+            .with_context(|| format!("failed to read patch {}", entry.name))?;
+        let file_patches = parse_patch(&text, entry.strip)
+            .with_context(|| format!("failed to parse patch {}", entry.name))?;
+        for fp in file_patches {
+            // the file to patch; for a rename or copy, its result goes to
+            // new_path instead
+            let source = match (&fp.old_path, &fp.new_path) {
+                (Some(old), _) if self.root.join(old).exists() => old,
+                (_, Some(new)) => new,
+                (Some(old), None) => old,
+                (None, None) => debcargo_bail!("patch {} has a hunk for /dev/null", entry.name),
+            };
+            let content = match self.read(source)? {
+                Some(data) => String::from_utf8(data)
+                    .with_context(|| format!("cannot patch non-UTF-8 file {}", source.display()))?,
+                None => String::new(),
+            };
+            let patched = fp.apply(&content).map_err(|e| {
+                format_err!(
+                    "patch {} does not apply to {}: {}",
+                    entry.name,
+                    source.display(),
+                    e
+                )
+            })?;
+            match fp.new_path {
+                None => fs::remove_file(self.root.join(source))?,
+                Some(ref target) => {
+                    if target != source {
+                        self.read(target)?;
+                        if fp.rename {
+                            fs::remove_file(self.root.join(source))?;
+                        }
+                    }
+                    let file = self.root.join(target);
+                    fs::create_dir_all(file.parent().unwrap())?;
+                    fs::write(&file, patched)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Apply every patch in the quilt series in `patches_dir` to `srcdir`, like
+/// `quilt push -a`. If any patch fails, the tree is restored to its original
+/// state and an error naming the failing patch and hunk is returned.
+pub fn push_all(patches_dir: &Path, srcdir: &Path) -> Result<AppliedPatches> {
+    let mut applied = AppliedPatches {
+        root: srcdir.to_path_buf(),
+        originals: BTreeMap::new(),
+    };
+    for entry in read_series(patches_dir)? {
+        if let Err(e) = applied.apply(patches_dir, &entry) {
+            applied.revert()?;
+            return Err(e);
+        }
+    }
+    Ok(applied)
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,126 @@
+use super::{parse_patch, push_all, read_series, SeriesEntry};
+
+use std::fs;
+
+const CARGO_TOML: &str = "\
+[package]
+name = \"foo\"
+version = \"1.0.0\"
+
+[dependencies]
+bar = \"0.3\"
+baz = \"1\"
+";
+
+const RELAX_BAR: &str = "\
+Description: relax bar dependency
+--- a/Cargo.toml
++++ b/Cargo.toml
+@@ -5,3 +5,3 @@
+ [dependencies]
+-bar = \"0.3\"
++bar = \"0.4\"
+ baz = \"1\"
+";
+
+#[test]
+fn apply_at_offset() {
+    let patches = parse_patch(RELAX_BAR, 1).unwrap();
+    assert_eq!(patches.len(), 1);
+    let shifted = format!("# a comment\n\n{}", CARGO_TOML);
+    let patched = patches[0].apply(&shifted).unwrap();
+    assert_eq!(patched, shifted.replace("0.3", "0.4"));
+}
+
+#[test]
+fn failed_hunk_is_reported() {
+    let patches = parse_patch(RELAX_BAR, 1).unwrap();
+    let err = patches[0]
+        .apply(&CARGO_TOML.replace("0.3", "0.5"))
+        .unwrap_err();
+    assert!(err
+        .to_string()
+        .starts_with("hunk #1 (@@ -5,3 +5,3 @@) FAILED"));
+}
+
+#[test]
+fn no_newline_at_end_of_file() {
+    let patch = "\
+--- a/f
++++ b/f
+@@ -1,2 +1,2 @@
+ one
+-two
+\\ No newline at end of file
++three
+";
+    let patches = parse_patch(patch, 1).unwrap();
+    assert_eq!(patches[0].apply("one\ntwo").unwrap(), "one\nthree\n");
+}
+
+#[test]
+fn push_and_revert_series() {
+    let srcdir = tempfile::tempdir().unwrap();
+    let patches_dir = tempfile::tempdir().unwrap();
+    fs::write(srcdir.path().join("Cargo.toml"), CARGO_TOML).unwrap();
+    fs::write(patches_dir.path().join("relax-bar.patch"), RELAX_BAR).unwrap();
+    fs::write(
+        patches_dir.path().join("series"),
+        "# comment\nrelax-bar.patch -p1\n",
+    )
+    .unwrap();
+
+    assert_eq!(
+        read_series(patches_dir.path()).unwrap(),
+        vec![SeriesEntry {
+            name: "relax-bar.patch".to_string(),
+            strip: 1
+        }]
+    );
+
+    let applied = push_all(patches_dir.path(), srcdir.path()).unwrap();
+    let patched = fs::read_to_string(srcdir.path().join("Cargo.toml")).unwrap();
+    assert!(patched.contains("bar = \"0.4\""));
+    applied.revert().unwrap();
+    let reverted = fs::read_to_string(srcdir.path().join("Cargo.toml")).unwrap();
+    assert_eq!(reverted, CARGO_TOML);
+}
+
+#[test]
+fn rename_and_copy() {
+    let srcdir = tempfile::tempdir().unwrap();
+    let patches_dir = tempfile::tempdir().unwrap();
+    fs::create_dir(srcdir.path().join("src")).unwrap();
+    fs::write(srcdir.path().join("src/old.rs"), "one\ntwo\n").unwrap();
+    fs::write(srcdir.path().join("src/kept.rs"), "three\n").unwrap();
+    let patch = "\
+diff --git a/src/old.rs b/src/new.rs
+similarity index 50%
+rename from src/old.rs
+rename to src/new.rs
+--- a/src/old.rs
++++ b/src/new.rs
+@@ -1,2 +1,2 @@
+ one
+-two
++2
+diff --git a/src/kept.rs b/src/copy.rs
+similarity index 100%
+copy from src/kept.rs
+copy to src/copy.rs
+";
+    fs::write(patches_dir.path().join("move.patch"), patch).unwrap();
+    fs::write(patches_dir.path().join("series"), "move.patch\n").unwrap();
+
+    let applied = push_all(patches_dir.path(), srcdir.path()).unwrap();
+    let read = |p: &str| fs::read_to_string(srcdir.path().join(p)).ok();
+    assert_eq!(read("src/old.rs"), None);
+    assert_eq!(read("src/new.rs").as_deref(), Some("one\n2\n"));
+    assert_eq!(read("src/kept.rs").as_deref(), Some("three\n"));
+    assert_eq!(read("src/copy.rs").as_deref(), Some("three\n"));
+
+    applied.revert().unwrap();
+    assert_eq!(read("src/old.rs").as_deref(), Some("one\ntwo\n"));
+    assert_eq!(read("src/new.rs"), None);
+    assert_eq!(read("src/copy.rs"), None);
+}
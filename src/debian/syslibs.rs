@@ -0,0 +1,197 @@
+//! Debian packages providing the native libraries that `-sys` crates link to,
+//! so that they can be depended on without hand-written overrides.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::Context;
+use serde::Deserialize;
+
+use crate::errors::*;
+
+/// Keyed on the `links` value in Cargo.toml.
+const BY_LINKS: &[(&str, &str)] = &[
+    ("archive", "libarchive-dev"),
+    ("asound", "libasound2-dev"),
+    ("blkid", "libblkid-dev"),
+    ("bzip2", "libbz2-dev"),
+    ("cairo", "libcairo2-dev"),
+    ("cap", "libcap-dev"),
+    ("clang", "libclang-dev"),
+    ("curl", "libcurl4-gnutls-dev | libcurl-dev"),
+    ("dbus", "libdbus-1-dev"),
+    ("drm", "libdrm-dev"),
+    ("expat", "libexpat1-dev"),
+    ("fontconfig", "libfontconfig-dev"),
+    ("freetype", "libfreetype-dev"),
+    ("fuse", "libfuse-dev"),
+    ("fuse3", "libfuse3-dev"),
+    ("gbm", "libgbm-dev"),
+    ("gio", "libglib2.0-dev"),
+    ("git2", "libgit2-dev"),
+    ("glib", "libglib2.0-dev"),
+    ("gmp", "libgmp-dev"),
+    ("gobject", "libglib2.0-dev"),
+    ("gpg-error", "libgpg-error-dev"),
+    ("gpgme", "libgpgme-dev"),
+    ("jpeg", "libjpeg-dev"),
+    ("lmdb", "liblmdb-dev"),
+    ("lz4", "liblz4-dev"),
+    ("lzma", "liblzma-dev"),
+    ("mount", "libmount-dev"),
+    ("nettle", "nettle-dev"),
+    ("nghttp2", "libnghttp2-dev"),
+    ("onig", "libonig-dev"),
+    ("openssl", "libssl-dev"),
+    ("pango", "libpango1.0-dev"),
+    ("pcap", "libpcap-dev"),
+    ("pcre2", "libpcre2-dev"),
+    ("png", "libpng-dev"),
+    ("pq", "libpq-dev"),
+    ("seccomp", "libseccomp-dev"),
+    ("selinux", "libselinux1-dev"),
+    ("sodium", "libsodium-dev"),
+    ("sqlite3", "libsqlite3-dev"),
+    ("ssh2", "libssh2-1-dev"),
+    ("systemd", "libsystemd-dev"),
+    ("udev", "libudev-dev"),
+    ("usb-1.0", "libusb-1.0-0-dev"),
+    ("webp", "libwebp-dev"),
+    ("xcb", "libxcb1-dev"),
+    ("xkbcommon", "libxkbcommon-dev"),
+    ("xml2", "libxml2-dev"),
+    ("yaml", "libyaml-dev"),
+    ("z", "zlib1g-dev"),
+    ("zmq", "libzmq3-dev"),
+    ("zstd", "libzstd-dev"),
+];
+
+/// Keyed on the crate name, for crates without a `links` value or whose
+/// `links` value is ambiguous. Takes precedence over `BY_LINKS`.
+const BY_CRATE: &[(&str, &str)] = &[
+    ("gtk-sys", "libgtk-3-dev"),
+    ("gtk4-sys", "libgtk-4-dev"),
+    ("libsystemd-sys", "libsystemd-dev"),
+    ("x11", "libx11-dev"),
+    ("yeslogic-fontconfig-sys", "libfontconfig-dev"),
+];
+
+/// A mapping file, for extending or overriding the built-in database.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+struct SysLibMapFile {
+    links: BTreeMap<String, String>,
+    crates: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SysLibMap {
+    links: BTreeMap<String, String>,
+    crates: BTreeMap<String, String>,
+}
+
+/// What a crate needs from the system.
+#[derive(Debug, PartialEq, Eq)]
+pub enum SysLib {
+    /// The crate does not link to a native library.
+    None,
+    /// The crate links to a native library from these Debian packages.
+    Packages(Vec<String>),
+    /// The crate links to a native library that is not in the database.
+    Unknown(String),
+}
+
+fn to_map(entries: &[(&str, &str)]) -> BTreeMap<String, String> {
+    entries
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+}
+
+impl Default for SysLibMap {
+    fn default() -> Self {
+        SysLibMap {
+            links: to_map(BY_LINKS),
+            crates: to_map(BY_CRATE),
+        }
+    }
+}
+
+impl SysLibMap {
+    /// The built-in database, extended by the mapping file at `path` if any.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let mut map = Self::default();
+        if let Some(path) = path {
+            let text = fs::read_to_string(path)
+                .with_context(|| format!("failed to read syslib_map {}", path.display()))?;
+            map.extend_from_str(&text)
+                .with_context(|| format!("failed to parse syslib_map {}", path.display()))?;
+        }
+        Ok(map)
+    }
+
+    /// Add the entries of a mapping file, overriding any existing ones.
+    pub fn extend_from_str(&mut self, text: &str) -> Result<()> {
+        let file: SysLibMapFile = toml::from_str(text)?;
+        self.links.extend(file.links);
+        self.crates.extend(file.crates);
+        Ok(())
+    }
+
+    /// Look up the Debian packages for a crate's native library. An empty
+    /// value in the database means that no package is needed, e.g. because
+    /// the library is always bundled.
+    pub fn lookup(&self, crate_name: &str, links: Option<&str>) -> SysLib {
+        let value = match (self.crates.get(crate_name), links) {
+            (Some(v), _) => v,
+            (None, None) => return SysLib::None,
+            (None, Some(l)) => match self.links.get(l) {
+                Some(v) => v,
+                None => return SysLib::Unknown(l.to_string()),
+            },
+        };
+        SysLib::Packages(
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|p| !p.is_empty())
+                .map(String::from)
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SysLib, SysLibMap};
+
+    #[test]
+    fn lookup_links_and_crates() {
+        let mut map = SysLibMap::default();
+        let packages = |p: &[&str]| SysLib::Packages(p.iter().map(|s| s.to_string()).collect());
+        assert_eq!(
+            map.lookup("openssl-sys", Some("openssl")),
+            packages(&["libssl-dev"])
+        );
+        assert_eq!(map.lookup("x11", None), packages(&["libx11-dev"]));
+        assert_eq!(map.lookup("serde", None), SysLib::None);
+        assert_eq!(
+            map.lookup("foo-sys", Some("foo")),
+            SysLib::Unknown("foo".to_string())
+        );
+
+        map.extend_from_str(
+            "[links]\nfoo = \"libfoo-dev, libbar-dev\"\n\n[crates]\nopenssl-sys = \"\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            map.lookup("foo-sys", Some("foo")),
+            packages(&["libfoo-dev", "libbar-dev"])
+        );
+        assert_eq!(map.lookup("openssl-sys", Some("openssl")), packages(&[]));
+        assert!(map
+            .extend_from_str("[link]\nfoo = \"libfoo-dev\"\n")
+            .is_err());
+    }
+}
@@ -0,0 +1,157 @@
+//! Generation of debian/upstream/metadata (DEP-12) from the crate's manifest.
+
+use std::fs;
+use std::path::Path;
+
+use cargo::core::manifest::ManifestMetadata;
+
+use super::copyright::{cargo_vcs_info, repository_clone_url};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Forge {
+    GitHub,
+    GitLab,
+}
+
+fn forge(url: &str) -> Option<Forge> {
+    let host = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))?
+        .split('/')
+        .next()?;
+    match host {
+        "github.com" => Some(Forge::GitHub),
+        "gitlab.com" | "salsa.debian.org" => Some(Forge::GitLab),
+        _ if host.starts_with("gitlab.") => Some(Forge::GitLab),
+        _ => None,
+    }
+}
+
+/// The crate's directory in its repository, as recorded by cargo.
+fn vcs_path(srcdir: &Path) -> Option<String> {
+    let info = cargo_vcs_info(srcdir)?;
+    Some(info.get("path_in_vcs")?.as_str()?.to_string()).filter(|p| !p.is_empty())
+}
+
+/// The upstream changelog in the crate's top-level directory, if any.
+fn changelog_file(srcdir: &Path) -> Option<String> {
+    let mut names = fs::read_dir(srcdir)
+        .ok()?
+        .filter_map(|e| e.ok()?.file_name().into_string().ok())
+        .filter(|name| {
+            let stem = name.split('.').next().unwrap().to_lowercase();
+            ["changelog", "changes", "history", "news", "releases"].contains(&stem.as_str())
+        })
+        .collect::<Vec<_>>();
+    names.sort();
+    names.into_iter().next()
+}
+
+/// The DEP-12 fields for a crate, in order. `changelog` is the path of the
+/// upstream changelog in the repository, if there is one.
+fn metadata_fields(
+    repository: Option<&str>,
+    documentation: Option<&str>,
+    changelog: Option<&str>,
+) -> Vec<(&'static str, String)> {
+    let mut fields = Vec::new();
+    let repository = repository.map(repository_clone_url);
+    let forge = repository.and_then(forge);
+    if let (Some(repo), Some(forge)) = (repository, forge) {
+        let repo = repo.trim_end_matches(".git");
+        let (issues, blob) = match forge {
+            Forge::GitHub => ("issues", "blob"),
+            Forge::GitLab => ("-/issues", "-/blob"),
+        };
+        fields.push(("Bug-Database", format!("{}/{}", repo, issues)));
+        if let Some(changelog) = changelog {
+            fields.push(("Changelog", format!("{}/{}/HEAD/{}", repo, blob, changelog)));
+        }
+    }
+    if let Some(documentation) = documentation {
+        fields.push(("Documentation", documentation.to_string()));
+    }
+    match (repository, forge) {
+        (Some(repo), Some(_)) => {
+            let repo = repo.trim_end_matches(".git");
+            fields.push(("Repository", format!("{}.git", repo)));
+            fields.push(("Repository-Browse", repo.to_string()));
+        }
+        (Some(repo), None) => fields.push(("Repository", repo.to_string())),
+        (None, _) => (),
+    }
+    fields
+}
+
+/// The contents of debian/upstream/metadata for the crate extracted in
+/// `srcdir`, or None if the manifest has nothing to put in it. A homepage on
+/// a known forge is taken as the repository if there is no repository.
+pub fn upstream_metadata(meta: &ManifestMetadata, srcdir: &Path) -> Option<String> {
+    let repository = meta
+        .repository
+        .as_deref()
+        .or_else(|| meta.homepage.as_deref().filter(|h| forge(h).is_some()));
+    let changelog = changelog_file(srcdir).map(|file| match vcs_path(srcdir) {
+        Some(dir) => format!("{}/{}", dir.trim_end_matches('/'), file),
+        None => file,
+    });
+    let fields = metadata_fields(
+        repository,
+        meta.documentation.as_deref(),
+        changelog.as_deref(),
+    );
+    if fields.is_empty() {
+        return None;
+    }
+    let mut metadata = "---\n".to_string();
+    for (key, value) in fields {
+        metadata.push_str(&format!("{}: {}\n", key, value));
+    }
+    Some(metadata)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::metadata_fields;
+
+    #[test]
+    fn dep12_fields() {
+        let fields = |r, d, c| {
+            metadata_fields(r, d, c)
+                .into_iter()
+                .map(|(k, v)| format!("{}: {}", k, v))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            fields(
+                Some("https://github.com/BurntSushi/ripgrep/tree/master/crates/globset"),
+                Some("https://docs.rs/globset"),
+                Some("crates/globset/CHANGELOG.md"),
+            ),
+            [
+                "Bug-Database: https://github.com/BurntSushi/ripgrep/issues",
+                "Changelog: https://github.com/BurntSushi/ripgrep/blob/HEAD/crates/globset/CHANGELOG.md",
+                "Documentation: https://docs.rs/globset",
+                "Repository: https://github.com/BurntSushi/ripgrep.git",
+                "Repository-Browse: https://github.com/BurntSushi/ripgrep",
+            ]
+        );
+        assert_eq!(
+            fields(Some("https://gitlab.com/foo/bar.git"), None, None),
+            [
+                "Bug-Database: https://gitlab.com/foo/bar/-/issues",
+                "Repository: https://gitlab.com/foo/bar.git",
+                "Repository-Browse: https://gitlab.com/foo/bar",
+            ]
+        );
+        assert_eq!(
+            fields(
+                Some("https://git.sr.ht/~foo/bar"),
+                None,
+                Some("CHANGELOG.md")
+            ),
+            ["Repository: https://git.sr.ht/~foo/bar"]
+        );
+        assert!(fields(None, None, Some("CHANGELOG.md")).is_empty());
+    }
+}
@@ -0,0 +1,139 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use anyhow::format_err;
+use clap::Parser;
+use serde::Serialize;
+
+use crate::config;
+use crate::crates::{show_dep, transitive_deps};
+use crate::debian::control::{base_deb_name, deb_feature_name, deb_name, dsc_name};
+use crate::debian::{self, deb_deps};
+use crate::errors::Result;
+use crate::package::{PackageExtractArgs, PackageInitArgs, PackageProcess};
+use crate::profile::Profile;
+
+#[derive(Debug, Clone, Parser)]
+pub struct InfoArgs {
+    #[command(flatten)]
+    init: PackageInitArgs,
+    /// Print the effective config instead, merged over the defaults file,
+    /// with the file that each value comes from.
+    #[arg(long)]
+    show_config: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct CrateDebInfo<'a> {
+    crate_id: String,
+    checksum: Option<&'a str>,
+    source_name: String,
+    package_name: &'a str,
+    upstream_version: &'a str,
+    orig_tarball: &'a Path,
+    features: BTreeMap<&'a str, FeatureInfo<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+struct FeatureInfo<'a> {
+    /// Features enabled by this one, transitively.
+    features: BTreeSet<&'a str>,
+    /// Crate dependencies pulled in by this feature, transitively.
+    dependencies: Vec<String>,
+    /// The binary package that provides this feature.
+    binary_package: String,
+    /// `dependencies` translated into Debian dependencies.
+    deb_dependencies: Vec<String>,
+}
+
+/// Describe how a crate maps onto Debian packages, as JSON.
+///
+/// If a config is given, its overlay and patches are applied first, so that
+/// the information matches what `debcargo package` would generate.
+pub fn info(args: InfoArgs, profile: Option<&Profile>) -> Result<String> {
+    if args.show_config {
+        let path = match args.init.config {
+            Some(ref p) => p,
+            None => debcargo_bail!("--show-config requires --config"),
+        };
+        return config::show_layered(path, args.init.defaults.for_config(path).as_deref());
+    }
+    let mut process = PackageProcess::init(args.init, profile)?;
+    let scratch = if process.config_path.is_some() {
+        let scratch = tempfile::Builder::new()
+            .prefix("debcargo")
+            .tempdir_in(".")?;
+        process.extract(PackageExtractArgs {
+            directory: Some(scratch.path().join(process.deb_info.package_source_dir())),
+        })?;
+        process.apply_overrides()?;
+        Some(scratch)
+    } else {
+        None
+    };
+    let PackageProcess {
+        crate_info,
+        deb_info,
+        config,
+        ..
+    } = &process;
+
+    let features_with_deps = crate_info.all_dependencies_and_features();
+    let (provides, _) = debian::feature_packages(config, features_with_deps.clone())?;
+    let pkgbase = deb_info.package_name();
+    let mut binary_packages = BTreeMap::new();
+    for (feature, provided) in provides {
+        let name = match feature {
+            "" => deb_name(config.profile(), pkgbase),
+            _ => deb_feature_name(config.profile(), pkgbase, feature),
+        };
+        for f in provided {
+            binary_packages.insert(f, name.clone());
+        }
+        binary_packages.insert(feature, name);
+    }
+
+    let binary_package = |feature: &str| -> Result<String> {
+        match binary_packages.get(feature) {
+            Some(p) => Ok(p.clone()),
+            // merged into a feature whose name differs only by _ vs -
+            None => binary_packages
+                .iter()
+                .find(|(f, _)| base_deb_name(f) == base_deb_name(feature))
+                .map(|(_, p)| p.clone())
+                .ok_or_else(|| format_err!("no binary package provides feature {}", feature)),
+        }
+    };
+
+    let mut features = BTreeMap::new();
+    for &feature in features_with_deps.keys() {
+        let (feature_deps, deps) = transitive_deps(&features_with_deps, feature)?;
+        let deps = deps
+            .into_iter()
+            .map(|d| (show_dep(&d), d))
+            .collect::<BTreeMap<_, _>>();
+        features.insert(
+            feature,
+            FeatureInfo {
+                features: feature_deps.into_iter().collect(),
+                deb_dependencies: deb_deps(config, &deps.values().cloned().collect::<Vec<_>>())?,
+                dependencies: deps.into_keys().collect(),
+                binary_package: binary_package(feature)?,
+            },
+        );
+    }
+
+    let json = serde_json::to_string_pretty(&CrateDebInfo {
+        crate_id: crate_info.package_id().to_string(),
+        checksum: crate_info.checksum(),
+        source_name: dsc_name(config.profile(), pkgbase),
+        package_name: pkgbase,
+        upstream_version: deb_info.deb_upstream_version(),
+        orig_tarball: deb_info.orig_tarball_path(),
+        features,
+    })?;
+    if let Some(scratch) = scratch {
+        scratch.close()?;
+    }
+    Ok(json)
+}
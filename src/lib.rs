@@ -1,9 +1,14 @@
 #[macro_use]
 pub mod errors;
+pub mod archive;
 pub mod config;
 pub mod crates;
 pub mod debian;
 mod util;
 
 pub mod build_order;
+pub mod info;
 pub mod package;
+pub mod profile;
+pub mod release;
+pub mod workspace;
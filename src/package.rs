@@ -1,13 +1,19 @@
-use std::path::PathBuf;
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
 
 use anyhow::Context;
 
 use clap::{crate_version, Parser};
 
-use crate::config::Config;
-use crate::crates::CrateInfo;
+use crate::archive::{ArchiveIndex, DepStatus};
+use crate::config::{Config, DefaultsArgs};
+use crate::crates::{show_dep, CrateInfo, CrateSource};
+use crate::debian::binaries::{ControlPackages, PackageChanges};
 use crate::debian::{self, DebInfo};
 use crate::errors::Result;
+use crate::profile::Profile;
 use crate::util;
 
 pub struct PackageProcess {
@@ -35,6 +41,14 @@ pub struct PackageInitArgs {
     /// TOML file providing package-specific options.
     #[arg(long)]
     pub config: Option<PathBuf>,
+    /// Where to get the crate from: crates-io, the name of a registry in
+    /// cargo's config, local-registry:PATH for a `cargo local-registry`
+    /// directory, or directory:PATH for a `cargo vendor` directory. Overrides
+    /// the registry key in the config.
+    #[arg(long, visible_alias = "source")]
+    pub registry: Option<CrateSource>,
+    #[command(flatten)]
+    pub defaults: DefaultsArgs,
 }
 
 #[derive(Debug, Clone, Parser)]
@@ -53,9 +67,26 @@ pub struct PackageExecuteArgs {
     /// Guess extra values for d/copyright. Might be slow.
     #[arg(long)]
     pub copyright_guess_harder: bool,
+    /// Keep one d/copyright paragraph per file with a copyright notice,
+    /// instead of grouping them by directory. Useful for audits.
+    #[arg(long)]
+    pub copyright_per_file: bool,
     /// Don't write back hint files or d/changelog to the source overlay directory.
     #[arg(long)]
     pub no_overlay_write_back: bool,
+    /// Fail if there are binary packages that the overlay's debian/control or
+    /// control.debcargo.hint does not have, as they have to go through the
+    /// NEW queue.
+    #[arg(long)]
+    pub fail_on_new: bool,
+}
+
+#[derive(Debug, Clone, Parser)]
+pub struct PackageDiffArgs {
+    /// Existing packaging to compare against. This is either a source tree
+    /// containing a debian/ directory, or an overlay directory.
+    #[arg(long)]
+    pub against: PathBuf,
 }
 
 impl PackageProcess {
@@ -66,7 +97,13 @@ impl PackageProcess {
         config: Config,
     ) -> Result<Self> {
         crate_info.set_includes_excludes(config.orig_tar_excludes(), config.orig_tar_whitelist());
-        let deb_info = DebInfo::new(&crate_info, crate_version!(), config.semver_suffix);
+        crate_info.set_arch_table(config.arch_table()?);
+        let deb_info = DebInfo::new(
+            config.profile(),
+            &crate_info,
+            crate_version!(),
+            config.semver_suffix,
+        );
 
         Ok(Self {
             crate_info,
@@ -80,23 +117,33 @@ impl PackageProcess {
         })
     }
 
-    pub fn init(init_args: PackageInitArgs) -> Result<Self> {
+    /// `profile` overrides the one that the config selects, if given.
+    pub fn init(init_args: PackageInitArgs, profile: Option<&Profile>) -> Result<Self> {
         let crate_name = &init_args.crate_name;
         let version = init_args.version.as_deref();
         let config = init_args.config;
 
-        let (config_path, config) = match config {
+        let (config_path, mut config) = match config {
             Some(path) => {
-                let config = Config::parse(&path).context("failed to parse debcargo.toml")?;
+                let defaults = init_args.defaults.for_config(&path);
+                let config = Config::parse_with_defaults(&path, defaults.as_deref())
+                    .context("failed to parse debcargo.toml")?;
                 (Some(path), config)
             }
             None => (None, Config::default()),
         };
+        config.override_profile(profile);
 
         let crate_path = config.crate_src_path(config_path.as_deref());
         let crate_info = match crate_path {
             Some(p) => CrateInfo::new_with_local_crate(crate_name, version, &p)?,
-            None => CrateInfo::new(crate_name, version)?,
+            None => {
+                let source = match init_args.registry {
+                    Some(source) => source,
+                    None => config.crate_source(config_path.as_deref())?,
+                };
+                CrateInfo::new(crate_name, version, &source)?
+            }
         };
 
         Self::new(crate_info, config_path, config)
@@ -186,6 +233,15 @@ impl PackageProcess {
         let temp_output_dir = temp_output_dir.as_ref().unwrap();
         // vars read; begin stage
 
+        // read before the new hint is written back over it
+        let overlay = config.overlay_dir(config_path.as_deref());
+        let old_control = overlay.as_ref().and_then(|p| {
+            ["control", "control.debcargo.hint"]
+                .iter()
+                .find_map(|f| fs::read_to_string(p.join(f)).ok())
+        });
+        let overlay_has_control = matches!(overlay, Some(ref p) if p.join("control").exists());
+
         debian::prepare_debian_folder(
             crate_info,
             deb_info,
@@ -195,13 +251,257 @@ impl PackageProcess {
             temp_output_dir,
             args.changelog_ready,
             args.copyright_guess_harder,
+            args.copyright_per_file,
             !args.no_overlay_write_back,
         )?;
 
+        if let Some(old_control) = old_control {
+            let new_control = output_dir.join("debian").join(if overlay_has_control {
+                "control.debcargo.hint"
+            } else {
+                "control"
+            });
+            let changes = PackageChanges::new(
+                &ControlPackages::parse(&old_control),
+                &ControlPackages::parse(&fs::read_to_string(new_control)?),
+            );
+            for line in changes.report() {
+                debcargo_warn!("{}", line);
+            }
+            if args.fail_on_new && changes.needs_new() {
+                debcargo_bail!("Binary packages were added, which have to go through NEW");
+            }
+        }
+
         // stage finished; set vars
         Ok(())
     }
 
+    /// Compare the generated debian/ directory against existing packaging and
+    /// print a unified diff for every file that differs.
+    ///
+    /// If `against` contains a debian/ directory it is treated as a source
+    /// tree and all files are compared. Otherwise it is treated as an overlay
+    /// directory, in which case only the files it contains, and any hints that
+    /// would be written back to it, are compared.
+    ///
+    /// Returns whether any differences were found.
+    pub fn diff_debian_folder(&self, against: &Path) -> Result<bool> {
+        let output_dir = self.output_dir.as_ref().unwrap();
+        let generated = output_dir.join("debian");
+        let (existing, is_overlay) = if against.join("debian").is_dir() {
+            (against.join("debian"), false)
+        } else {
+            (against.to_path_buf(), true)
+        };
+
+        let generated_files = util::relative_files(&generated)?;
+        let existing_files = util::relative_files(&existing)?;
+        let to_compare = if is_overlay {
+            existing_files
+                .iter()
+                .chain(
+                    generated_files
+                        .iter()
+                        .filter(|f| util::hint_file_for(f).is_some()),
+                )
+                .collect::<BTreeSet<_>>()
+        } else {
+            existing_files
+                .iter()
+                .chain(generated_files.iter())
+                .collect::<BTreeSet<_>>()
+        };
+
+        let read_if_exists = |path: PathBuf| match fs::read(path) {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        };
+        let mut differs = false;
+        for f in to_compare {
+            let old = read_if_exists(existing.join(f))?;
+            let new = read_if_exists(generated.join(f))?;
+            if old == new {
+                continue;
+            }
+            differs = true;
+            let name = Path::new("debian").join(f);
+            print!(
+                "{}",
+                util::unified_diff(
+                    &format!("a/{}", name.display()),
+                    &format!("b/{}", name.display()),
+                    old.as_deref(),
+                    new.as_deref(),
+                )
+            );
+        }
+        Ok(differs)
+    }
+
+    /// Warn about mistakes in the config file, as found by `Config::validate`.
+    /// This should happen after `apply_overrides` so that features added or
+    /// removed by patches are taken into account.
+    ///
+    /// Returns whether no problems were found.
+    pub fn check_config(&self) -> Result<bool> {
+        let config_path = match self.config_path.as_deref() {
+            Some(p) => p,
+            None => return Ok(true),
+        };
+        let features_with_deps = self.crate_info.all_dependencies_and_features();
+        let features = features_with_deps.keys().copied().collect::<Vec<_>>();
+        let problems = self.config.validate(config_path, &features)?;
+        for p in &problems {
+            debcargo_warn!("{}: {}", config_path.display(), p);
+        }
+        Ok(problems.is_empty())
+    }
+
+    /// Upstream version of the last released entry in the overlay's
+    /// debian/changelog, if there is one.
+    pub fn last_packaged_version(&self) -> Result<Option<String>> {
+        let overlay = match self.config.overlay_dir(self.config_path.as_deref()) {
+            Some(p) => p,
+            None => return Ok(None),
+        };
+        match fs::read_to_string(overlay.join("changelog")) {
+            Ok(data) => debian::changelog::last_packaged_version(&data),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Check that the patches in the overlay still apply to the extracted
+    /// crate, warning about the first one that doesn't. This leaves the
+    /// extracted crate untouched, and must happen before `apply_overrides`.
+    ///
+    /// Returns whether all patches applied.
+    pub fn check_patches(&self) -> Result<bool> {
+        let output_dir = self.output_dir.as_ref().unwrap();
+        let patches_dir = match self.config.overlay_dir(self.config_path.as_deref()) {
+            Some(p) => p.join("patches"),
+            None => return Ok(true),
+        };
+        if !patches_dir.join("series").exists() {
+            return Ok(true);
+        }
+        match debian::patches::push_all(&patches_dir, output_dir) {
+            Ok(applied) => {
+                applied.revert()?;
+                Ok(true)
+            }
+            Err(e) => {
+                debcargo_warn!("{:#}", e);
+                debcargo_warn!(
+                    "Refresh or drop the patch in {}, then try again.",
+                    util::rel_p(&patches_dir, &std::env::current_dir()?)
+                );
+                Ok(false)
+            }
+        }
+    }
+
+    /// Warn about `packages."lib+FEATURE"` overrides in the config whose
+    /// feature no longer exists in the crate, suggesting what it may have been
+    /// renamed to.
+    ///
+    /// Returns whether all overrides refer to existing features.
+    pub fn check_feature_overrides(&self) -> bool {
+        let features_with_deps = self.crate_info.all_dependencies_and_features();
+        let features = features_with_deps.keys().copied().collect::<Vec<_>>();
+        let stale = self.config.stale_feature_overrides(&features);
+        for key in &stale {
+            let feature = &key["lib+".len()..];
+            match util::closest_match(feature, features.iter().copied()) {
+                Some(f) => debcargo_warn!(
+                    "Config has overrides for feature \"{}\" which no longer exists; did it become \"{}\"?",
+                    feature,
+                    f
+                ),
+                None => debcargo_warn!(
+                    "Config has overrides for feature \"{}\" which no longer exists; remove [packages.\"{}\"]",
+                    feature,
+                    key
+                ),
+            }
+        }
+        stale.is_empty()
+    }
+
+    /// Extract the crate and apply the overlay as `debcargo update` does,
+    /// refusing to continue unless this is a new upstream version that the
+    /// overlay's patches still apply to. Unless `allow_stale_overrides`, this
+    /// also refuses if the config has overrides for features that are gone.
+    pub fn update(
+        &mut self,
+        extract: PackageExtractArgs,
+        allow_stale_overrides: bool,
+    ) -> Result<()> {
+        let new_version = self.deb_info.deb_upstream_version().to_string();
+        match self.last_packaged_version()? {
+            None => debcargo_bail!("no released version in the overlay's debian/changelog"),
+            Some(v) if v == new_version => debcargo_bail!(
+                "version {} is already packaged; use `debcargo package` for a new revision",
+                v
+            ),
+            Some(v) => debcargo_info!("Updating from {} to {}", v, new_version),
+        }
+        log::info!("extracting crate");
+        self.extract(extract)?;
+        log::info!("checking patches");
+        if !self.check_patches()? {
+            debcargo_bail!("patches in the overlay do not apply to {}", new_version);
+        }
+        log::info!("applying overlay and patches");
+        self.apply_overrides()?;
+        if !self.check_feature_overrides() && !allow_stale_overrides {
+            debcargo_bail!(
+                "config has overrides for features that {} no longer has; fix them, or \
+                 pass --allow-stale-overrides to continue anyway",
+                new_version
+            );
+        }
+        Ok(())
+    }
+
+    /// Print the crate's direct dependencies that are not yet packaged in
+    /// the archive, along with the Debian packages they are expected as.
+    pub fn archive_summary(&self, archive: &ArchiveIndex) -> Result<()> {
+        use cargo::core::dependency::DepKind;
+        let mut unpackaged = Vec::new();
+        for dep in self.crate_info.dependencies() {
+            if dep.kind() == DepKind::Development || !self.crate_info.is_debian_dependency(dep) {
+                continue;
+            }
+            let status = match archive.status(dep)? {
+                DepStatus::Satisfied(_) => continue,
+                DepStatus::MissingFeatures(version, features) => {
+                    format!("archive {} lacks features {}", version, features.join(", "))
+                }
+                DepStatus::Missing => "not in the archive".to_string(),
+                DepStatus::Mismatch(versions) => {
+                    format!("archive only has {}", versions.join(", "))
+                }
+            };
+            let deb_deps = debian::deb_deps(&self.config, std::slice::from_ref(dep))?;
+            unpackaged.push((show_dep(dep), status, deb_deps));
+        }
+        if unpackaged.is_empty() {
+            debcargo_info!("All dependencies are satisfied by the archive.");
+            return Ok(());
+        }
+        debcargo_warn!("Dependencies not satisfied by the archive:");
+        for (dep, status, deb_deps) in unpackaged {
+            debcargo_warn!("\t •  {}: {}", dep, status);
+            for d in deb_deps {
+                debcargo_warn!("\t    {}", d);
+            }
+        }
+        Ok(())
+    }
+
     pub fn post_package_checks(&self) -> Result<()> {
         let Self {
             config_path,
@@ -249,3 +549,6 @@ impl PackageProcess {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,136 @@
+use super::{PackageExtractArgs, PackageInitArgs, PackageProcess};
+use crate::config::DefaultsArgs;
+
+use std::fs;
+
+const CARGO_TOML: &str = "\
+[package]
+name = \"foo\"
+version = \"1.1.0\"
+edition = \"2021\"
+description = \"Foo\"
+license = \"MIT\"
+
+[features]
+new = []
+";
+
+const CONFIG: &str = "\
+overlay = \".\"
+crate_src_path = \"../../../crate\"
+";
+
+const STALE_OVERRIDE: &str = "
+[packages.\"lib+gone\"]
+summary = \"Removed feature\"
+";
+
+fn changelog(version: &str) -> String {
+    format!(
+        "\
+rust-foo ({}-1) unstable; urgency=medium
+
+  * Package foo {} from crates.io using debcargo 2.6.1
+
+ -- Jane Doe <jane@example.com>  Fri, 16 Oct 2026 00:00:00 +0000
+",
+        version, version
+    )
+}
+
+/// Set up a local crate foo 1.1.0, and an overlay for it in
+/// conf/foo/debian with the given config and debian/changelog, then run the
+/// checks of `debcargo update` on it.
+fn update(
+    config: &str,
+    changelog: Option<&str>,
+    patch: Option<&str>,
+    allow_stale_overrides: bool,
+) -> anyhow::Result<()> {
+    let dir = tempfile::tempdir().unwrap();
+    let crate_dir = dir.path().join("crate");
+    fs::create_dir_all(crate_dir.join("src")).unwrap();
+    fs::write(crate_dir.join("Cargo.toml"), CARGO_TOML).unwrap();
+    fs::write(crate_dir.join("src/lib.rs"), "").unwrap();
+
+    let overlay = dir.path().join("conf/foo/debian");
+    fs::create_dir_all(&overlay).unwrap();
+    fs::write(overlay.join("debcargo.toml"), config).unwrap();
+    if let Some(changelog) = changelog {
+        fs::write(overlay.join("changelog"), changelog).unwrap();
+    }
+    if let Some(patch) = patch {
+        fs::create_dir(overlay.join("patches")).unwrap();
+        fs::write(overlay.join("patches/series"), "fix.patch\n").unwrap();
+        fs::write(overlay.join("patches/fix.patch"), patch).unwrap();
+    }
+
+    let mut process = PackageProcess::init(
+        PackageInitArgs {
+            crate_name: "foo".to_string(),
+            version: None,
+            config: Some(overlay.join("debcargo.toml")),
+            registry: None,
+            defaults: DefaultsArgs { defaults: None },
+        },
+        None,
+    )?;
+    process.update(
+        PackageExtractArgs {
+            directory: Some(dir.path().join("foo-1.1.0")),
+        },
+        allow_stale_overrides,
+    )
+}
+
+fn assert_fails(result: anyhow::Result<()>, message: &str) {
+    let e = result.unwrap_err().to_string();
+    assert!(e.contains(message), "unexpected error: {}", e);
+}
+
+#[test]
+fn update_to_new_version() {
+    update(CONFIG, Some(&changelog("1.0.0")), None, false).unwrap();
+}
+
+#[test]
+fn update_without_released_version() {
+    assert_fails(
+        update(CONFIG, None, None, false),
+        "no released version in the overlay's debian/changelog",
+    );
+}
+
+#[test]
+fn update_to_same_version() {
+    assert_fails(
+        update(CONFIG, Some(&changelog("1.1.0")), None, false),
+        "version 1.1.0 is already packaged",
+    );
+}
+
+#[test]
+fn update_with_stale_patches() {
+    let patch = "\
+--- a/Cargo.toml
++++ b/Cargo.toml
+@@ -5,2 +5,2 @@
+ [features]
+-old = []
++older = []
+";
+    assert_fails(
+        update(CONFIG, Some(&changelog("1.0.0")), Some(patch), false),
+        "patches in the overlay do not apply to 1.1.0",
+    );
+}
+
+#[test]
+fn update_with_stale_overrides() {
+    let config = format!("{}{}", CONFIG, STALE_OVERRIDE);
+    assert_fails(
+        update(&config, Some(&changelog("1.0.0")), None, false),
+        "pass --allow-stale-overrides to continue anyway",
+    );
+    update(&config, Some(&changelog("1.0.0")), None, true).unwrap();
+}
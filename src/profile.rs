@@ -0,0 +1,150 @@
+//! Distribution-specific defaults for the generated packaging, similar to
+//! dpkg-vendor. Debian's are used unless another profile is selected with
+//! --profile, DEBCARGO_PROFILE or the profile key of a config. The selected
+//! profile is carried by each `Config`, see `Config::profile`.
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::Context;
+use serde::Deserialize;
+
+use crate::errors::*;
+
+pub const RUST_MAINT: &str =
+    "Debian Rust Maintainers <pkg-rust-maintainers@alioth-lists.debian.net>";
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default = "Profile::debian", deny_unknown_fields)]
+pub struct Profile {
+    pub name: String,
+    /// Maintainer of packages whose config does not set one.
+    pub maintainer: String,
+    /// If set, the Maintainer of every package, with the one it would
+    /// otherwise have moved to XSBC-Original-Maintainer, as Ubuntu does.
+    pub maintainer_override: Option<String>,
+    /// Prefix of source package names.
+    pub source_prefix: String,
+    /// Prefix of binary package names.
+    pub package_prefix: String,
+    /// Vcs-Git of packages whose config does not set one, as a template.
+    pub vcs_git: String,
+    /// Vcs-Browser of packages whose config does not set one, as a template.
+    pub vcs_browser: String,
+    /// Debhelper compat level of packages whose config does not set one.
+    pub debhelper_compat: u32,
+    /// Minimum version of dh-cargo to build-depend on.
+    pub dh_cargo: String,
+    /// Minimum version of dh-cargo that autopkgtests depend on.
+    pub dh_cargo_tests: String,
+    pub standards_version: String,
+    /// Distribution that `debcargo release` targets by default, if any.
+    pub distribution: Option<String>,
+}
+
+/// The debian profile, or the testing one if DEBCARGO_TESTING_RUZT=1, for
+/// compatibility with older test setups.
+impl Default for Profile {
+    fn default() -> Self {
+        if std::env::var("DEBCARGO_TESTING_RUZT") == Ok("1".to_string()) {
+            Profile::testing()
+        } else {
+            Profile::debian()
+        }
+    }
+}
+
+const BUILTIN: &[&str] = &["debian", "ubuntu", "testing"];
+
+impl Profile {
+    pub fn debian() -> Profile {
+        Profile {
+            name: "debian".to_string(),
+            maintainer: RUST_MAINT.to_string(),
+            maintainer_override: None,
+            source_prefix: "rust".to_string(),
+            package_prefix: "librust".to_string(),
+            vcs_git: "https://salsa.debian.org/rust-team/debcargo-conf.git [src/{name}]"
+                .to_string(),
+            vcs_browser: "https://salsa.debian.org/rust-team/debcargo-conf/tree/master/src/{name}"
+                .to_string(),
+            debhelper_compat: 13,
+            dh_cargo: "25".to_string(),
+            dh_cargo_tests: "31".to_string(),
+            standards_version: "4.6.2".to_string(),
+            distribution: Some("unstable".to_string()),
+        }
+    }
+
+    /// Ubuntu keeps the Debian packaging, but is the Maintainer of every
+    /// package it changes. Uploads target a release codename, so there is
+    /// no default distribution.
+    pub fn ubuntu() -> Profile {
+        Profile {
+            name: "ubuntu".to_string(),
+            maintainer_override: Some(
+                "Ubuntu Developers <ubuntu-devel-discuss@lists.ubuntu.com>".to_string(),
+            ),
+            distribution: None,
+            ..Profile::debian()
+        }
+    }
+
+    /// Debian's, but with package names that cannot clash with the archive,
+    /// to avoid accidentally installing official packages during tests.
+    pub fn testing() -> Profile {
+        Profile {
+            name: "testing".to_string(),
+            source_prefix: "ruzt".to_string(),
+            package_prefix: "libruzt".to_string(),
+            ..Profile::debian()
+        }
+    }
+
+    /// A built-in profile by name, or a custom one from a TOML file, whose
+    /// keys default to those of the debian profile. Relative paths are
+    /// resolved against `base_dir`, if given.
+    pub fn load(name: &str, base_dir: Option<&Path>) -> Result<Profile> {
+        match name {
+            "debian" => return Ok(Profile::debian()),
+            "ubuntu" => return Ok(Profile::ubuntu()),
+            "testing" => return Ok(Profile::testing()),
+            _ => (),
+        }
+        if !name.ends_with(".toml") {
+            debcargo_bail!(
+                "unknown profile {}; expected one of {}, or a TOML file",
+                name,
+                BUILTIN.join(", ")
+            );
+        }
+        let path = match base_dir {
+            Some(d) => d.join(name),
+            None => Path::new(name).to_path_buf(),
+        };
+        let mut profile: Profile = toml::from_str(&fs::read_to_string(&path)?)
+            .with_context(|| format!("failed to parse profile {}", path.display()))?;
+        if profile.name == "debian" {
+            profile.name = path.display().to_string();
+        }
+        Ok(profile)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Profile;
+
+    #[test]
+    fn builtin_profiles() {
+        let ubuntu = Profile::load("ubuntu", None).unwrap();
+        assert_eq!(ubuntu.maintainer, Profile::debian().maintainer);
+        assert!(ubuntu.maintainer_override.is_some());
+        assert_eq!(ubuntu.distribution, None);
+        assert_eq!(
+            Profile::load("testing", None).unwrap().source_prefix,
+            "ruzt"
+        );
+        assert!(Profile::load("fedora", None).is_err());
+    }
+}
@@ -0,0 +1,174 @@
+//! Finalizing the unreleased debian/changelog entry in an overlay, so that the
+//! package is ready to be uploaded.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::Context;
+use chrono::{DateTime, FixedOffset};
+use clap::Parser;
+use regex::Regex;
+use walkdir::WalkDir;
+
+use crate::config::Config;
+use crate::debian::changelog::{self, ChangelogEntry, ChangelogIterator};
+use crate::errors::*;
+use crate::profile::Profile;
+use crate::util;
+
+#[derive(Debug, Clone, Parser)]
+pub struct ReleaseArgs {
+    /// TOML file of the package to release. Its overlay directory holds the
+    /// debian/changelog to finalize.
+    #[arg(long)]
+    config: PathBuf,
+    /// Distribution to target, e.g. unstable, experimental or
+    /// bookworm-backports. Defaults to that of the profile, unstable for
+    /// debian.
+    #[arg(long)]
+    distribution: Option<String>,
+}
+
+/// Hint files in the overlay whose overridden file no longer exists, which
+/// debcargo would not write back and so are left over from older packaging.
+fn stale_hints(overlay: &Path) -> Result<Vec<PathBuf>> {
+    let mut stale = Vec::new();
+    for entry in WalkDir::new(overlay) {
+        let entry = entry?;
+        if let Some(file) = util::hint_file_for(entry.path()) {
+            if !file.exists() {
+                stale.push(entry.path().to_path_buf());
+            }
+        }
+    }
+    Ok(stale)
+}
+
+/// Set the distribution and date of the top entry of a changelog, which must
+/// be one generated by debcargo that was not yet released.
+fn finalize(
+    changelog_data: &str,
+    distribution: &str,
+    date: DateTime<FixedOffset>,
+) -> Result<String> {
+    let top = match ChangelogIterator::from(changelog_data).next() {
+        Some(x) => x,
+        None => debcargo_bail!("changelog has no entries"),
+    };
+    let mut entry = ChangelogEntry::from_str(top)?;
+    if !entry.distribution.contains(changelog::DEFAULT_DIST) {
+        debcargo_bail!(
+            "{} {} is already released to {}",
+            entry.source,
+            entry.version,
+            entry.distribution
+        );
+    }
+    if let Some(item) = entry.items.iter().find(|i| i.contains("FIXME")) {
+        debcargo_bail!("changelog entry has a FIXME: {}", item.trim());
+    }
+    entry.distribution = distribution.to_string();
+    entry.date = date;
+    let rest = &changelog_data[top.len()..];
+    Ok(if rest.is_empty() {
+        entry.to_string()
+    } else {
+        format!("{}\n{}", entry, rest)
+    })
+}
+
+/// Finalize the top changelog entry in the overlay of a package, refusing if
+/// the overlay still has FIXMEs or stale hint files.
+/// `profile` overrides the one that the config selects, if given.
+pub fn release(args: ReleaseArgs, profile: Option<&Profile>) -> Result<()> {
+    let mut config = Config::parse(&args.config)?;
+    config.override_profile(profile);
+    let overlay = match config.overlay_dir(Some(&args.config)) {
+        Some(p) => p,
+        None => debcargo_bail!("{} sets no overlay", args.config.display()),
+    };
+    let distribution = match args.distribution {
+        Some(d) => d,
+        None => match config.profile().distribution {
+            Some(ref d) => d.clone(),
+            None => debcargo_bail!(
+                "profile {} has no default distribution; pass --distribution",
+                config.profile().name
+            ),
+        },
+    };
+    if !Regex::new(r"^[-+0-9a-z.]+$")
+        .unwrap()
+        .is_match(&distribution)
+    {
+        debcargo_bail!("invalid distribution: {}", distribution);
+    }
+
+    let changelog_path = overlay.join("changelog");
+    let changelog_data = fs::read_to_string(&changelog_path)
+        .with_context(|| format!("could not read {}", changelog_path.display()))?;
+    // the unreleased entry is itself a FIXME, which finalize checks separately
+    let fixmes = util::lookup_fixmes(&overlay)?
+        .into_iter()
+        .filter(|f| f != &changelog_path)
+        .collect::<Vec<_>>();
+    let stale = stale_hints(&overlay)?;
+    if !fixmes.is_empty() || !stale.is_empty() {
+        let curdir = std::env::current_dir()?;
+        if !fixmes.is_empty() {
+            debcargo_warn!("FIXME found in the following files.");
+            for f in &fixmes {
+                debcargo_warn!("\t •  {}", util::rel_p(f, &curdir));
+            }
+        }
+        if !stale.is_empty() {
+            debcargo_warn!("Stale hint files, for files that are no longer overridden:");
+            for f in &stale {
+                debcargo_warn!("\t •  {}", util::rel_p(f, &curdir));
+            }
+        }
+        debcargo_bail!("{} is not ready for release", overlay.display());
+    }
+
+    let released = finalize(&changelog_data, &distribution, changelog::local_now())?;
+    fs::write(&changelog_path, released)?;
+    debcargo_info!("Released {} to {}", changelog_path.display(), distribution);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::finalize;
+    use chrono::DateTime;
+
+    #[test]
+    fn finalize_changelog() {
+        let date = DateTime::parse_from_rfc2822("Sat, 17 Oct 2026 12:00:00 +0000").unwrap();
+        let older = "rust-foo (1.0.0-1) unstable; urgency=medium\n\n  \
+                     * Package foo 1.0.0 from crates.io using debcargo 2.6.0\n\n \
+                     -- Jo Doe <jo@example.org>  Thu, 01 Jan 2026 12:00:00 +0000\n";
+        let data = format!(
+            "rust-foo (1.1.0-1) UNRELEASED-FIXME-AUTOGENERATED-DEBCARGO; urgency=medium\n\n  \
+             * Package foo 1.1.0 from crates.io using debcargo 2.6.0\n\n \
+             -- Jo Doe <jo@example.org>  Fri, 16 Oct 2026 09:30:00 +0200\n\n{}",
+            older
+        );
+        assert_eq!(
+            finalize(&data, "experimental", date).unwrap(),
+            format!(
+                "rust-foo (1.1.0-1) experimental; urgency=medium\n\n  \
+                 * Package foo 1.1.0 from crates.io using debcargo 2.6.0\n\n \
+                 -- Jo Doe <jo@example.org>  Sat, 17 Oct 2026 12:00:00 +0000\n\n{}",
+                older
+            )
+        );
+        assert!(finalize(older, "unstable", date).is_err());
+        assert!(finalize(
+            &data.replace("* Package", "* FIXME: Package"),
+            "unstable",
+            date
+        )
+        .is_err());
+    }
+}
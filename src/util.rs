@@ -10,9 +10,9 @@ use std::os::unix::ffi::OsStrExt;
 #[cfg(unix)]
 use std::os::unix::fs::symlink;
 use std::path::{Path, PathBuf};
-use std::process::Command;
 
 use itertools::Itertools;
+use similar::TextDiff;
 use walkdir::WalkDir;
 
 pub const HINT_SUFFIX: &str = ".debcargo.hint";
@@ -106,6 +106,101 @@ pub fn copy_tree(oldtree: &Path, newtree: &Path) -> Result<(), Error> {
     Ok(())
 }
 
+/// List all non-directory entries under a directory, relative to it.
+pub fn relative_files(dir: &Path) -> Result<BTreeSet<PathBuf>, Error> {
+    let mut files = BTreeSet::new();
+    for entry in WalkDir::new(dir) {
+        let entry = entry?;
+        if !entry.file_type().is_dir() {
+            files.insert(entry.path().strip_prefix(dir).unwrap().to_path_buf());
+        }
+    }
+    Ok(files)
+}
+
+/// Render a unified diff between two optional file contents, where None means
+/// the file does not exist on that side.
+pub fn unified_diff(
+    old_name: &str,
+    new_name: &str,
+    old: Option<&[u8]>,
+    new: Option<&[u8]>,
+) -> String {
+    let old_name = if old.is_some() { old_name } else { "/dev/null" };
+    let new_name = if new.is_some() { new_name } else { "/dev/null" };
+    let old = std::str::from_utf8(old.unwrap_or_default());
+    let new = std::str::from_utf8(new.unwrap_or_default());
+    match (old, new) {
+        (Ok(old), Ok(new)) => TextDiff::from_lines(old, new)
+            .unified_diff()
+            .header(old_name, new_name)
+            .to_string(),
+        _ => format!("Binary files {} and {} differ\n", old_name, new_name),
+    }
+}
+
+/// Levenshtein edit distance between two strings, counted in chars.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut prev = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let sub = prev[j] + usize::from(ca != *cb);
+            cur.push(sub.min(prev[j + 1] + 1).min(cur[j] + 1));
+        }
+        prev = cur;
+    }
+    prev[b.len()]
+}
+
+/// Pick the candidate closest to `name`, if any is close enough to be a
+/// plausible typo or rename of it.
+pub fn closest_match<'a>(
+    name: &str,
+    candidates: impl IntoIterator<Item = &'a str>,
+) -> Option<&'a str> {
+    let max = std::cmp::max(1, name.chars().count() / 3);
+    candidates
+        .into_iter()
+        .map(|c| (edit_distance(name, c), c))
+        .filter(|(d, _)| *d <= max)
+        .min()
+        .map(|(_, c)| c)
+}
+
+/// Parse the paragraphs of a deb822 file, such as debian/control or an apt
+/// index, into maps from field names to values. The lines of multi-line
+/// values are joined with newlines, without their leading whitespace.
+pub fn parse_deb822(text: &str) -> Vec<BTreeMap<String, String>> {
+    let mut paragraphs = Vec::new();
+    let mut paragraph: BTreeMap<String, String> = BTreeMap::new();
+    let mut last_field = None;
+    for line in text.lines() {
+        if line.trim().is_empty() {
+            if !paragraph.is_empty() {
+                paragraphs.push(std::mem::take(&mut paragraph));
+            }
+            last_field = None;
+        } else if line.starts_with('#') {
+            continue;
+        } else if line.starts_with([' ', '\t']) {
+            if let Some(value) = last_field.as_ref().and_then(|f| paragraph.get_mut(f)) {
+                value.push('\n');
+                value.push_str(line.trim());
+            }
+        } else if let Some((field, value)) = line.split_once(':') {
+            let field = field.trim().to_string();
+            paragraph.insert(field.clone(), value.trim().to_string());
+            last_field = Some(field);
+        }
+    }
+    if !paragraph.is_empty() {
+        paragraphs.push(paragraph);
+    }
+    paragraphs
+}
+
 pub fn show_vec_with<'a, T, F>(it: impl IntoIterator<Item = &'a T>, f: F) -> String
 where
     T: 'a,
@@ -121,19 +216,6 @@ where
     show_vec_with(it, std::string::ToString::to_string)
 }
 
-pub fn expect_success(cmd: &mut Command, err: &str) {
-    match cmd.status() {
-        Ok(status) => {
-            if !status.success() {
-                panic!("{}", err);
-            }
-        }
-        Err(e) => {
-            panic!("{}\n{}", err, e);
-        }
-    }
-}
-
 pub(crate) fn traverse_depth<'a, V, F>(succ: &'a F, key: V) -> BTreeSet<V>
 where
     V: Ord + Copy + 'a,
@@ -276,3 +358,202 @@ where
         Ok(sort)
     }
 }
+
+struct Tarjan<V> {
+    index: BTreeMap<V, usize>,
+    lowlink: BTreeMap<V, usize>,
+    stack: Vec<V>,
+    on_stack: BTreeSet<V>,
+}
+
+impl<V: Ord + Clone> Tarjan<V> {
+    fn visit(&mut self, v: &V) {
+        let i = self.index.len();
+        self.index.insert(v.clone(), i);
+        self.lowlink.insert(v.clone(), i);
+        self.on_stack.insert(v.clone());
+        self.stack.push(v.clone());
+    }
+
+    fn lower(&mut self, v: &V, low: usize) {
+        let l = self.lowlink.get_mut(v).unwrap();
+        *l = (*l).min(low);
+    }
+}
+
+/// Strongly connected components of a graph, using Tarjan's algorithm.
+/// Components are returned in reverse topological order.
+pub fn strongly_connected_components<V>(succ: &BTreeMap<V, BTreeSet<V>>) -> Vec<BTreeSet<V>>
+where
+    V: Ord + Clone,
+{
+    let empty = BTreeSet::new();
+    let mut t = Tarjan {
+        index: BTreeMap::new(),
+        lowlink: BTreeMap::new(),
+        stack: Vec::new(),
+        on_stack: BTreeSet::new(),
+    };
+    let mut components = Vec::new();
+
+    for root in succ.keys().chain(succ.values().flatten()) {
+        if t.index.contains_key(root) {
+            continue;
+        }
+        // iterative depth-first search, to avoid overflowing the call stack
+        t.visit(root);
+        let mut call = vec![(root.clone(), succ.get(root).unwrap_or(&empty).iter())];
+        while let Some((v, children)) = call.last_mut() {
+            if let Some(w) = children.next() {
+                if !t.index.contains_key(w) {
+                    t.visit(w);
+                    call.push((w.clone(), succ.get(w).unwrap_or(&empty).iter()));
+                } else if t.on_stack.contains(w) {
+                    let low = t.index[w];
+                    t.lower(v, low);
+                }
+                continue;
+            }
+            let v = v.clone();
+            call.pop();
+            if let Some((u, _)) = call.last() {
+                let low = t.lowlink[&v];
+                t.lower(u, low);
+            }
+            if t.lowlink[&v] == t.index[&v] {
+                let mut component = BTreeSet::new();
+                loop {
+                    let w = t.stack.pop().unwrap();
+                    t.on_stack.remove(&w);
+                    let done = w == v;
+                    component.insert(w);
+                    if done {
+                        break;
+                    }
+                }
+                components.push(component);
+            }
+        }
+    }
+    components
+}
+
+/// A shortest cycle through the given nodes of a graph, e.g. a strongly
+/// connected component, as a list of nodes where each is followed by its
+/// successor and the last is followed by the first. None if there is no
+/// cycle within the nodes.
+pub fn shortest_cycle<V>(succ: &BTreeMap<V, BTreeSet<V>>, within: &BTreeSet<V>) -> Option<Vec<V>>
+where
+    V: Ord + Clone,
+{
+    let empty = BTreeSet::new();
+    let mut best: Option<Vec<V>> = None;
+    for start in within {
+        // breadth-first search back to start
+        let mut parent = BTreeMap::new();
+        let mut remain = VecDeque::from([start]);
+        'search: while let Some(v) = remain.pop_front() {
+            for w in succ.get(v).unwrap_or(&empty) {
+                if !within.contains(w) || parent.contains_key(w) {
+                    continue;
+                }
+                parent.insert(w, v);
+                if w == start {
+                    break 'search;
+                }
+                remain.push_back(w);
+            }
+        }
+        if !parent.contains_key(start) {
+            continue;
+        }
+        let mut cycle = vec![start.clone()];
+        let mut v = parent[start];
+        while v != start {
+            cycle.push(v.clone());
+            v = parent[v];
+        }
+        cycle[1..].reverse();
+        if best.as_ref().map_or(true, |b| cycle.len() < b.len()) {
+            best = Some(cycle);
+        }
+    }
+    best
+}
+
+/// Cycles through the given nodes of a graph, e.g. a strongly connected
+/// component, such that removing one edge of each leaves no cycle at all.
+/// Each is the shortest cycle left after removing the edges of the previous
+/// ones, and comes with the edge to remove: the first one that is
+/// `breakable`, or None if there is none, in which case its first edge is
+/// removed anyway to look for the remaining cycles.
+#[allow(clippy::type_complexity)]
+pub fn cycles_to_break<V, F>(
+    succ: &BTreeMap<V, BTreeSet<V>>,
+    within: &BTreeSet<V>,
+    mut breakable: F,
+) -> Vec<(Vec<V>, Option<(V, V)>)>
+where
+    V: Ord + Clone,
+    F: FnMut(&V, &V) -> bool,
+{
+    let mut succ = succ.clone();
+    let mut cycles = Vec::new();
+    while let Some(cycle) = shortest_cycle(&succ, within) {
+        let edge = |i: usize| (cycle[i].clone(), cycle[(i + 1) % cycle.len()].clone());
+        let chosen = (0..cycle.len()).map(edge).find(|(v, w)| breakable(v, w));
+        let (v, w) = chosen.clone().unwrap_or_else(|| edge(0));
+        succ.get_mut(&v).unwrap().remove(&w);
+        cycles.push((cycle, chosen));
+    }
+    cycles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cycles_to_break, shortest_cycle, strongly_connected_components};
+    use std::collections::{BTreeMap, BTreeSet};
+
+    fn graph(edges: &[(u32, u32)]) -> BTreeMap<u32, BTreeSet<u32>> {
+        let mut succ: BTreeMap<u32, BTreeSet<u32>> = BTreeMap::new();
+        for (a, b) in edges {
+            succ.entry(*a).or_default().insert(*b);
+        }
+        succ
+    }
+
+    #[test]
+    fn cycles_in_components() {
+        // 1 -> 2 -> 3 -> 1 with a shortcut 2 -> 1, and 3 -> 4 -> 5 -> 4
+        let succ = graph(&[(1, 2), (2, 3), (3, 1), (2, 1), (3, 4), (4, 5), (5, 4)]);
+        let sccs = strongly_connected_components(&succ);
+        assert_eq!(
+            sccs,
+            vec![BTreeSet::from([4, 5]), BTreeSet::from([1, 2, 3])]
+        );
+        assert_eq!(shortest_cycle(&succ, &sccs[0]), Some(vec![4, 5]));
+        assert_eq!(shortest_cycle(&succ, &sccs[1]), Some(vec![1, 2]));
+        assert_eq!(shortest_cycle(&succ, &BTreeSet::from([3])), None);
+    }
+
+    #[test]
+    fn independent_cycles_in_component() {
+        // 1 -> 2 -> 1 and 2 -> 3 -> 4 -> 2, which only share node 2
+        let succ = graph(&[(1, 2), (2, 1), (2, 3), (3, 4), (4, 2)]);
+        let sccs = strongly_connected_components(&succ);
+        assert_eq!(sccs, vec![BTreeSet::from([1, 2, 3, 4])]);
+        assert_eq!(
+            cycles_to_break(&succ, &sccs[0], |_, _| true),
+            vec![(vec![1, 2], Some((1, 2))), (vec![2, 3, 4], Some((2, 3))),]
+        );
+        // edges out of 2 cannot be broken
+        assert_eq!(
+            cycles_to_break(&succ, &sccs[0], |v, _| *v != 2),
+            vec![(vec![1, 2], Some((1, 2))), (vec![2, 3, 4], Some((3, 4))),]
+        );
+        assert_eq!(
+            cycles_to_break(&succ, &sccs[0], |_, _| false),
+            vec![(vec![1, 2], None), (vec![2, 3, 4], None)]
+        );
+    }
+}
@@ -0,0 +1,315 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use cargo::core::dependency::DepKind;
+use cargo::core::{Package, Shell, Workspace};
+use cargo::util::homedir;
+use cargo::util::toml::TomlManifest;
+use clap::Parser;
+
+use crate::config::{find_config, DefaultsArgs};
+use crate::crates::CrateInfo;
+use crate::debian::control::{deb_name, dsc_name};
+use crate::errors::*;
+use crate::package::{PackageExecuteArgs, PackageExtractArgs, PackageProcess};
+use crate::profile::Profile;
+use crate::util;
+
+#[derive(Debug, Clone, Parser)]
+pub struct WorkspaceArgs {
+    /// Root directory of the Cargo workspace.
+    workspace: PathBuf,
+    /// Directory for configs of the workspace members, looked up in the same
+    /// way as for build-order.
+    #[arg(long)]
+    config_dir: Option<PathBuf>,
+    #[command(flatten)]
+    defaults: DefaultsArgs,
+    /// Directory to put the packages in, each in its own subdirectory.
+    #[arg(long)]
+    directory: Option<PathBuf>,
+    #[command(flatten)]
+    finish: PackageExecuteArgs,
+}
+
+/// Members of the workspace that each member depends on, other than for
+/// development.
+fn member_deps<'a>(
+    members: &BTreeMap<&'a str, &'a Package>,
+) -> BTreeMap<&'a str, BTreeSet<&'a str>> {
+    let mut deps = BTreeMap::new();
+    for (&name, package) in members {
+        let member_deps: &mut BTreeSet<&str> = deps.entry(name).or_default();
+        for dep in package.dependencies() {
+            if dep.kind() == DepKind::Development || !dep.source_id().is_path() {
+                continue;
+            }
+            let (&dep_name, dep_package) = match members.get_key_value(dep.package_name().as_str())
+            {
+                Some(m) => m,
+                None => continue,
+            };
+            if !dep.version_req().matches(dep_package.version()) {
+                debcargo_warn!(
+                    "{} depends on {} {}, but the workspace has version {}",
+                    name,
+                    dep_name,
+                    dep.version_req(),
+                    dep_package.version()
+                );
+            }
+            member_deps.insert(dep_name);
+        }
+    }
+    deps
+}
+
+/// Members that a member depends on, directly or indirectly.
+fn transitive_member_deps<'a>(
+    deps: &BTreeMap<&'a str, BTreeSet<&'a str>>,
+    name: &'a str,
+) -> BTreeSet<&'a str> {
+    let mut seen = BTreeSet::new();
+    let mut todo = vec![name];
+    while let Some(n) = todo.pop() {
+        for &d in &deps[n] {
+            if seen.insert(d) {
+                todo.push(d);
+            }
+        }
+    }
+    seen
+}
+
+/// A member with its path dependencies on other members given the version of
+/// that member if they have none, as `cargo package` requires a version for
+/// all dependencies other than development ones.
+fn with_member_versions(
+    package: &Package,
+    members: &BTreeMap<&str, &Package>,
+    cargo_config: &cargo::Config,
+) -> Result<Package> {
+    let mut manifest = toml::Value::try_from(package.manifest().original())?;
+    let add_versions = |table: &mut toml::Value| {
+        for key in ["dependencies", "build-dependencies", "build_dependencies"] {
+            let deps = match table.get_mut(key).and_then(toml::Value::as_table_mut) {
+                Some(deps) => deps,
+                None => continue,
+            };
+            for (name, dep) in deps.iter_mut() {
+                let dep = match dep.as_table_mut() {
+                    Some(d) if d.contains_key("path") && !d.contains_key("version") => d,
+                    _ => continue,
+                };
+                let name = dep
+                    .get("package")
+                    .and_then(toml::Value::as_str)
+                    .unwrap_or(name);
+                if let Some(member) = members.get(name) {
+                    let version = member.version().to_string();
+                    dep.insert("version".to_string(), toml::Value::String(version));
+                }
+            }
+        }
+    };
+    add_versions(&mut manifest);
+    if let Some(targets) = manifest
+        .get_mut("target")
+        .and_then(toml::Value::as_table_mut)
+    {
+        targets.iter_mut().for_each(|(_, t)| add_versions(t));
+    }
+    let manifest: TomlManifest = manifest.try_into()?;
+    let (manifest, _) = TomlManifest::to_real_manifest(
+        &Rc::new(manifest),
+        package.package_id().source_id(),
+        package.root(),
+        cargo_config,
+    )?;
+    Ok(Package::new(manifest, package.manifest_path()))
+}
+
+/// A cargo config for packaging a member, in which crates.io is patched with
+/// the members that it depends on. `cargo package` resolves the dependencies
+/// of crates with binaries or examples, which would otherwise look up
+/// unpublished members on crates.io. The config is written to `scratch`,
+/// which must be inside the workspace so that its cargo config still applies.
+fn member_cargo_config(
+    scratch: &Path,
+    member_deps: &BTreeSet<&str>,
+    members: &BTreeMap<&str, &Package>,
+) -> Result<cargo::Config> {
+    let mut patches = toml::value::Table::new();
+    for &name in member_deps {
+        let mut patch = toml::value::Table::new();
+        let path = members[name].root().display().to_string();
+        patch.insert("path".to_string(), toml::Value::String(path));
+        patches.insert(name.to_string(), toml::Value::Table(patch));
+    }
+    let mut patch = toml::value::Table::new();
+    patch.insert("crates-io".to_string(), toml::Value::Table(patches));
+    let mut config = toml::value::Table::new();
+    config.insert("patch".to_string(), toml::Value::Table(patch));
+    fs::create_dir_all(scratch.join(".cargo"))?;
+    fs::write(
+        scratch.join(".cargo").join("config.toml"),
+        toml::to_string(&config)?,
+    )?;
+    let home = homedir(scratch).ok_or_else(|| format_err!("cannot find the cargo home"))?;
+    let mut cargo_config = cargo::Config::new(Shell::new(), scratch.to_path_buf(), home);
+    // apply settings such as net.offline from the workspace's cargo config
+    cargo_config.configure(0, false, None, false, false, false, &None, &[], &[])?;
+    Ok(cargo_config)
+}
+
+/// The crate info of a member, packaged without looking up other members on
+/// crates.io. `scratch` is as for `member_cargo_config`, and must outlive the
+/// crate info.
+fn member_crate_info(
+    name: &str,
+    members: &BTreeMap<&str, &Package>,
+    deps: &BTreeMap<&str, BTreeSet<&str>>,
+    scratch: &Path,
+) -> Result<CrateInfo> {
+    let cargo_config = member_cargo_config(scratch, &transitive_member_deps(deps, name), members)?;
+    let package = with_member_versions(members[name], members, &cargo_config)?;
+    CrateInfo::new_with_local_package(package, cargo_config)
+}
+
+/// Package every member of a Cargo workspace, dependencies first.
+///
+/// Path dependencies between members become dependencies on each other's
+/// Debian packages, the same as for any other crate dependency, and are
+/// resolved locally even if the members are not published. As with
+/// crate_src_path, all other dependencies must be published on crates.io.
+///
+/// `profile` overrides the one that the configs select, if given.
+pub fn package_workspace(args: WorkspaceArgs, profile: Option<&Profile>) -> Result<()> {
+    let cargo_config = cargo::Config::default()?;
+    let manifest_path = args.workspace.canonicalize()?.join("Cargo.toml");
+    let ws = Workspace::new(&manifest_path, &cargo_config)?;
+    let members = ws
+        .members()
+        .map(|p| (p.name().as_str(), p))
+        .collect::<BTreeMap<_, _>>();
+
+    let deps = member_deps(&members);
+    let default_profile = Profile::default();
+    let shown_profile = profile.unwrap_or(&default_profile);
+    for (name, dd) in &deps {
+        if !dd.is_empty() {
+            debcargo_info!(
+                "{} ({}) depends on workspace members: {}",
+                name,
+                dsc_name(shown_profile, name),
+                dd.iter()
+                    .map(|d| format!("{} ({})", d, deb_name(shown_profile, d)))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+    }
+    let roots = deps
+        .iter()
+        .filter(|(_, dd)| dd.is_empty())
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>();
+    // swap pred/succ since dependencies need to be packaged first
+    let order =
+        util::topo_sort(roots, util::succ_to_pred(&deps), deps.clone()).map_err(|remain| {
+            format_err!(
+                "workspace members have cyclic dependencies: {}",
+                util::show_vec(remain.keys())
+            )
+        })?;
+
+    let defaults = args.defaults.for_config_dir(args.config_dir.as_deref());
+    let target_dir = ws.target_dir().into_path_unlocked();
+    fs::create_dir_all(&target_dir)?;
+    for name in order {
+        let package = members[name];
+        let version = package.version().to_string();
+        debcargo_info!("Packaging workspace member {} {}", name, version);
+        let (config_path, mut config) = match args.config_dir.as_deref() {
+            None => (None, Default::default()),
+            Some(config_dir) => {
+                find_config(config_dir, defaults.as_deref(), name, package.version())?
+            }
+        };
+        config.override_profile(profile);
+        let scratch = tempfile::Builder::new()
+            .prefix("debcargo")
+            .tempdir_in(&target_dir)?;
+        let crate_info = member_crate_info(name, &members, &deps, scratch.path())?;
+        let mut process = PackageProcess::new(crate_info, config_path, config)?;
+        let output_dir = args
+            .directory
+            .as_deref()
+            .unwrap_or_else(|| Path::new("."))
+            .join(process.deb_info.package_source_dir());
+        process.extract(PackageExtractArgs {
+            directory: Some(output_dir),
+        })?;
+        process.apply_overrides()?;
+        process.check_config()?;
+        process.prepare_orig_tarball()?;
+        process.prepare_debian_folder(args.finish.clone())?;
+        process.post_package_checks()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::fs;
+
+    use cargo::core::Workspace;
+
+    use super::{member_crate_info, member_deps};
+
+    #[test]
+    fn unpublished_member_dependency() {
+        let ws_dir = tempfile::tempdir().unwrap();
+        let write = |path: &str, contents: &str| {
+            let path = ws_dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        };
+        write(
+            "Cargo.toml",
+            "[workspace]\nmembers = [\"foo\", \"foo-cli\"]\n",
+        );
+        write(".cargo/config.toml", "[net]\noffline = true\n");
+        let manifest = |name: &str, version: &str, deps: &str| {
+            format!(
+                "[package]\nname = \"{}\"\nversion = \"{}\"\nlicense = \"MIT\"\n\
+                 description = \"test\"\n\n[dependencies]\n{}",
+                name, version, deps
+            )
+        };
+        write("foo/Cargo.toml", &manifest("foo", "0.3.1", ""));
+        write("foo/src/lib.rs", "");
+        // a binary, for which cargo package resolves the dependencies
+        write(
+            "foo-cli/Cargo.toml",
+            &manifest("foo-cli", "1.0.0", "foo = { path = \"../foo\" }\n"),
+        );
+        write("foo-cli/src/main.rs", "fn main() {}\n");
+
+        let cargo_config = cargo::Config::default().unwrap();
+        let ws = Workspace::new(&ws_dir.path().join("Cargo.toml"), &cargo_config).unwrap();
+        let members = ws
+            .members()
+            .map(|p| (p.name().as_str(), p))
+            .collect::<BTreeMap<_, _>>();
+        let deps = member_deps(&members);
+        let scratch = ws_dir.path().join("target/scratch");
+        let crate_info = member_crate_info("foo-cli", &members, &deps, &scratch).unwrap();
+        let dep = &crate_info.dependencies()[0];
+        assert_eq!(dep.package_name().as_str(), "foo");
+        assert_eq!(dep.version_req().to_string(), "^0.3.1");
+    }
+}
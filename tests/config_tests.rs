@@ -1,6 +1,8 @@
 extern crate debcargo;
 
 use debcargo::config::{Config, PackageKey};
+use debcargo::crates::CrateSource;
+use debcargo::profile::Profile;
 use std::path::Path;
 
 #[test]
@@ -91,3 +93,90 @@ Debian Rust team.
 "
     );
 }
+
+#[test]
+fn validate_against_crate() {
+    let filepath = Path::new("tests/typo_override.toml");
+    let config = Config::parse(filepath).unwrap();
+
+    let problems = config
+        .validate(filepath, &["", "default", "color"])
+        .unwrap();
+    assert_eq!(
+        problems,
+        vec![
+            "unknown key sumary; did you mean summary?",
+            "unknown key source.vcs_gti; did you mean source.vcs_git?",
+            "packages.\"lib+colour\" does not match any binary package; \
+             did you mean packages.\"lib+color\"?",
+            "overlay tests/does-not-exist does not exist",
+        ]
+    );
+}
+
+#[test]
+fn crate_source_relative_to_config() {
+    let filepath = Path::new("tests/local_registry.toml");
+    let config = Config::parse(filepath).unwrap();
+    assert_eq!(
+        config.crate_source(Some(filepath)).unwrap(),
+        CrateSource::LocalRegistry("tests/../registry".into())
+    );
+    assert_eq!(
+        Config::default().crate_source(Some(filepath)).unwrap(),
+        CrateSource::CratesIo
+    );
+    assert_eq!(
+        "mirror".parse::<CrateSource>().unwrap(),
+        CrateSource::Registry("mirror".to_string())
+    );
+    assert!("vendor:foo".parse::<CrateSource>().is_err());
+}
+
+#[test]
+fn defaults_merged_under_config() {
+    let filepath = Path::new("tests/debcargo_override.toml");
+    let defaults = Path::new("tests/debcargo_defaults.toml");
+    let config = Config::parse_with_defaults(filepath, Some(defaults)).unwrap();
+
+    assert_eq!(config.maintainer(), "Jane Doe <jane@example.org>");
+    assert_eq!(config.uploaders.as_ref().unwrap().len(), 2);
+    assert_eq!(config.section(), Some("rust"));
+    assert_eq!(config.policy_version(), Some("4.6.2"));
+    // entries of [packages] are replaced as a whole
+    assert!(config.package_depends(PackageKey::Bin).is_none());
+    assert!(config.package_summary(PackageKey::Bin).is_some());
+    assert_eq!(
+        config.package_test_is_broken(PackageKey::BareLib),
+        Some(true)
+    );
+
+    let shown = debcargo::config::show_layered(filepath, Some(defaults)).unwrap();
+    assert!(shown.contains(
+        "maintainer = \"Jane Doe <jane@example.org>\"  # tests/debcargo_defaults.toml\n"
+    ));
+    assert!(shown.contains("source.section = \"rust\"  # tests/debcargo_override.toml\n"));
+    assert!(shown.contains("packages.lib.test_is_broken = true  # tests/debcargo_defaults.toml\n"));
+
+    // syslib_map from the defaults is relative to the defaults file
+    let filepath = Path::new("tests/configs/petgraph-0.4/debian/debcargo.toml");
+    let config = Config::parse_with_defaults(filepath, Some(defaults)).unwrap();
+    let syslib_map = config.syslib_map_path(Some(filepath)).unwrap();
+    assert!(syslib_map.is_absolute());
+    assert!(syslib_map.ends_with("tests/syslibs.toml"));
+}
+
+#[test]
+fn profile_per_config() {
+    let ubuntu = Config::parse(Path::new("tests/ubuntu_profile.toml")).unwrap();
+    let debian = Config::parse(Path::new("tests/debcargo_override.toml")).unwrap();
+    assert_eq!(ubuntu.profile().name, "ubuntu");
+    assert_eq!(debian.profile().name, "debian");
+    assert_eq!(ubuntu.maintainer(), debian.maintainer());
+
+    let mut config = ubuntu.clone();
+    config.override_profile(Some(&Profile::testing()));
+    assert_eq!(config.profile().source_prefix, "ruzt");
+    config.override_profile(None);
+    assert_eq!(config.profile().name, "testing");
+}